@@ -0,0 +1,288 @@
+use std::mem;
+use crate::cpu::{Cpu, CpuStatus};
+use crate::cpu::state::CpuState;
+use crate::memory::Bus;
+use crate::EmulationError;
+
+/// Why the CPU accessed the bus.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccessKind {
+    OpcodeFetch,
+    Read,
+    Write,
+    /// A read whose value is discarded, made while the CPU is busy with something else.
+    DummyRead,
+    /// The unmodified value written back by read-modify-write instructions.
+    DummyWrite,
+}
+
+/// A bus access made by the CPU on a single cycle.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BusAccess {
+    pub address: u16,
+    pub value: u8,
+    pub kind: AccessKind,
+}
+
+// The state an instruction starts from, which it is re-run from on every cycle.
+#[derive(Copy, Clone)]
+struct StartState {
+    register_a: u8,
+    register_x: u8,
+    register_y: u8,
+    register_sp: u8,
+    register_pc: u16,
+    status_flags: CpuStatus,
+    halted: bool,
+    nmi_pending: bool,
+    irq_line: bool,
+    delayed_interrupt_flag: Option<bool>,
+}
+
+/// An instruction being executed one cycle at a time by `Cpu::tick`.
+pub(crate) struct PartialInstruction {
+    start: StartState,
+    accesses: Vec<BusAccess>,
+    ticks: u8,
+}
+
+/// Instructions are written to run all at once, so ticking re-runs the current one from its start
+/// on every cycle. Accesses made on earlier cycles are replayed from the log, the next one goes to
+/// the bus, and the access after it stops the run with `EmulationError::CycleEnded`, so the
+/// instruction never goes on with values it hasn't read yet.
+///
+/// This only works because operations change nothing but the state in `StartState`, which is put
+/// back before each run, and reach the bus only through `Cpu::access`. Operations must keep it so.
+pub(crate) struct Replay {
+    log: Vec<BusAccess>,
+    position: usize,
+    performed: bool,
+    // An NMI raised since the instruction started, until BRK takes it
+    late_nmi: bool,
+    // Logs every access without writing, for `Cpu::preview`
+    preview: bool,
+}
+
+impl PartialInstruction {
+    /// Takes a snapshot back to the start of the instruction.
+    pub(super) fn rewind(&self, state: &mut CpuState) {
+        let start = &self.start;
+        state.register_a = start.register_a;
+        state.register_x = start.register_x;
+        state.register_y = start.register_y;
+        state.register_sp = start.register_sp;
+        state.register_pc = start.register_pc;
+        state.status_flags = start.status_flags;
+        state.halted = start.halted;
+        state.nmi_pending |= start.nmi_pending;
+        state.delayed_interrupt_flag = start.delayed_interrupt_flag;
+        state.cycles -= self.ticks as u64;
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Advances the CPU by a single cycle, returning the bus access made on it. Unlike `step`,
+    /// this makes the dummy reads and writes of the real CPU, such as the read from the wrong
+    /// page on indexed addressing and the double write of read-modify-write instructions.
+    pub fn tick(&mut self) -> Result<BusAccess, EmulationError> {
+        if self.halted {
+            return Err(EmulationError::Halted);
+        }
+        let mut partial = match self.partial_instruction.take() {
+            Some(partial) => partial,
+            None => PartialInstruction {
+                start: self.start_state(),
+                accesses: Vec::new(),
+                ticks: 0,
+            },
+        };
+
+        // Interrupt lines may have changed since the instruction started, but only the state it
+        // was polled with can be replayed.
+        let irq_line = self.irq_line;
+        let late_nmi = self.nmi_pending && !partial.start.nmi_pending;
+        self.restore_start_state(&partial.start);
+        self.replay = Some(Replay {
+            log: mem::take(&mut partial.accesses),
+            position: 0,
+            performed: false,
+            late_nmi,
+            preview: false,
+        });
+        let result = self.execute();
+        let replay = self.replay.take().unwrap();
+        partial.accesses = replay.log;
+        let execution = match result {
+            Ok(execution) => Some(execution),
+            Err(EmulationError::CycleEnded) => {
+                self.restore_start_state(&partial.start);
+                None
+            }
+            Err(e) => {
+                self.halted = true;
+                return Err(e);
+            }
+        };
+        self.irq_line = irq_line;
+        self.nmi_pending |= if execution.is_some() { replay.late_nmi } else { late_nmi };
+        let access = *partial.accesses.last().unwrap();
+
+        match execution {
+            Some(execution) if execution.cycles == 0 => {
+                // The instruction halted instead of running, so the fetch doesn't take a cycle
+                self.complete(&execution);
+                return Err(EmulationError::Halted);
+            }
+            Some(execution) => {
+                // Operations make an access on every cycle, so the last one ends the instruction
+                debug_assert_eq!(partial.accesses.len(), execution.cycles as usize, "cycles without a bus access");
+                self.cycles += 1;
+                self.complete(&execution);
+            }
+            None => {
+                partial.ticks += 1;
+                self.cycles += 1;
+                self.partial_instruction = Some(partial);
+            }
+        }
+        Ok(access)
+    }
+
+    /// Runs the instruction at PC, or the interrupt about to be serviced, and returns the bus
+    /// accesses `tick` would make, without changing the CPU or the bus. Reads are peeks, so they
+    /// have no side effects, and an access that fails ends the preview early.
+    pub fn preview(&mut self) -> Vec<BusAccess> {
+        let state = self.start_state();
+        self.replay = Some(Replay {
+            log: Vec::new(),
+            position: 0,
+            performed: false,
+            late_nmi: false,
+            preview: true,
+        });
+        let result = self.execute();
+        let log = self.replay.take().unwrap().log;
+        if let Ok(execution) = result {
+            debug_assert!(execution.cycles == 0 || log.len() == execution.cycles as usize, "cycles without a bus access");
+        }
+        self.restore_start_state(&state);
+        log
+    }
+
+    /// Whether an instruction started by `tick` has cycles left.
+    pub fn mid_instruction(&self) -> bool {
+        self.partial_instruction.is_some()
+    }
+
+    pub(super) fn finish_instruction(&mut self) -> Result<u8, EmulationError> {
+        let mut cycles = self.partial_instruction.as_ref().map_or(0, |partial| partial.ticks);
+        while self.mid_instruction() {
+            self.tick()?;
+            cycles += 1;
+        }
+        Ok(cycles)
+    }
+
+    fn start_state(&self) -> StartState {
+        StartState {
+            register_a: self.register_a,
+            register_x: self.register_x,
+            register_y: self.register_y,
+            register_sp: self.register_sp,
+            register_pc: self.register_pc,
+            status_flags: self.status_flags,
+            halted: self.halted,
+            nmi_pending: self.nmi_pending,
+            irq_line: self.irq_line,
+            delayed_interrupt_flag: self.delayed_interrupt_flag,
+        }
+    }
+
+    fn restore_start_state(&mut self, state: &StartState) {
+        self.register_a = state.register_a;
+        self.register_x = state.register_x;
+        self.register_y = state.register_y;
+        self.register_sp = state.register_sp;
+        self.register_pc = state.register_pc;
+        self.status_flags = state.status_flags;
+        self.halted = state.halted;
+        self.nmi_pending = state.nmi_pending;
+        self.irq_line = state.irq_line;
+        self.delayed_interrupt_flag = state.delayed_interrupt_flag;
+    }
+
+    // Takes an NMI raised while the instruction runs, which only ticking can see.
+    pub(super) fn take_late_nmi(&mut self) -> bool {
+        self.replay.as_mut().is_some_and(|replay| mem::take(&mut replay.late_nmi))
+    }
+
+    pub(super) fn fetch_opcode(&mut self, address: u16) -> Result<u8, EmulationError> {
+        self.access(address, 0, AccessKind::OpcodeFetch)
+    }
+
+    pub(super) fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
+        self.access(address, 0, AccessKind::Read)
+    }
+
+    pub(super) fn read_word(&mut self, address: u16) -> Result<u16, EmulationError> {
+        let lo = self.read(address)?;
+        let hi = self.read(address.wrapping_add(1))?;
+        Ok(u16::from_le_bytes([lo, hi]))
+    }
+
+    pub(super) fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
+        self.access(address, value, AccessKind::Write)?;
+        Ok(())
+    }
+
+    // Dummy accesses only matter to code that watches the bus cycle by cycle, so `step` skips them.
+    pub(super) fn dummy_read(&mut self, address: u16) -> Result<(), EmulationError> {
+        if self.replay.is_some() {
+            self.access(address, 0, AccessKind::DummyRead)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn dummy_write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
+        if self.replay.is_some() {
+            self.access(address, value, AccessKind::DummyWrite)?;
+        }
+        Ok(())
+    }
+
+    fn access(&mut self, address: u16, value: u8, kind: AccessKind) -> Result<u8, EmulationError> {
+        if let Some(replay) = &mut self.replay {
+            if replay.preview {
+                let value = match kind {
+                    AccessKind::Write | AccessKind::DummyWrite => value,
+                    _ => self.bus.peek(address)?,
+                };
+                replay.log.push(BusAccess { address, value, kind });
+                return Ok(value);
+            }
+            if let Some(access) = replay.log.get(replay.position) {
+                replay.position += 1;
+                return Ok(access.value);
+            }
+            if replay.performed {
+                return Err(EmulationError::CycleEnded);
+            }
+            replay.performed = true;
+        }
+
+        let value = match kind {
+            AccessKind::Write | AccessKind::DummyWrite => {
+                self.bus.write(address, value)?;
+                value
+            }
+            // The value is discarded, so a read the bus rejects is harmless
+            AccessKind::DummyRead => self.bus.read(address).unwrap_or(0),
+            AccessKind::OpcodeFetch | AccessKind::Read => self.bus.read(address)?,
+        };
+        if let Some(replay) = &mut self.replay {
+            replay.log.push(BusAccess { address, value, kind });
+            replay.position += 1;
+        }
+        Ok(value)
+    }
+}
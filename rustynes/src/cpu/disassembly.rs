@@ -8,6 +8,7 @@ pub struct Instruction {
     pub instruction: &'static str,
     pub addressing_mode: AddressingMode,
     pub length: u16,
+    pub unofficial: bool,
 }
 
 pub struct Trace {
@@ -23,6 +24,7 @@ pub struct Trace {
     pub data_address: u16,
     pub data_at_address: u16,
     pub status_flags: CpuStatus,
+    pub cycles: u64,
 }
 
 
@@ -35,52 +37,65 @@ impl Default for Instruction {
             instruction: "??",
             addressing_mode: AddressingMode::Implied,
             length: 1,
+            unofficial: false,
         }
     }
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Unofficial opcodes are marked the same way nestest.log does
+        let mnemonic = if self.unofficial {
+            format!("*{}", self.instruction)
+        } else {
+            self.instruction.to_string()
+        };
 
         f.pad(&match self.addressing_mode {
             AddressingMode::Immediate => {
-                format!("{} #${:02X}", self.instruction.to_uppercase(), self.operands[0])
+                format!("{} #${:02X}", mnemonic.to_uppercase(), self.operands[0])
             }
             AddressingMode::ZeroPage => {
-                format!("{} ${:02X}", self.instruction.to_uppercase(), self.operands[0])
+                format!("{} ${:02X}", mnemonic.to_uppercase(), self.operands[0])
             }
             AddressingMode::ZeroPageX => {
-                format!("{} ${:02X},X", self.instruction.to_uppercase(), self.operands[0])
+                format!("{} ${:02X},X", mnemonic.to_uppercase(), self.operands[0])
             }
             AddressingMode::ZeroPageY => {
-                format!("{} ${:02X},Y", self.instruction.to_uppercase(), self.operands[0])
+                format!("{} ${:02X},Y", mnemonic.to_uppercase(), self.operands[0])
             }
             AddressingMode::Absolute => {
-                format!("{} ${:02X}{:02X}", self.instruction.to_uppercase(), self.operands[1], self.operands[0])
+                format!("{} ${:02X}{:02X}", mnemonic.to_uppercase(), self.operands[1], self.operands[0])
             }
             AddressingMode::AbsoluteX => {
-                format!("{} ${:02X}{:02X},X", self.instruction.to_uppercase(), self.operands[1], self.operands[0])
+                format!("{} ${:02X}{:02X},X", mnemonic.to_uppercase(), self.operands[1], self.operands[0])
             }
             AddressingMode::AbsoluteY => {
-                format!("{} ${:02X}{:02X},Y", self.instruction.to_uppercase(), self.operands[1], self.operands[0])
+                format!("{} ${:02X}{:02X},Y", mnemonic.to_uppercase(), self.operands[1], self.operands[0])
             }
             AddressingMode::Indirect => {
-                format!("{} (${:02X}{:02X})", self.instruction.to_uppercase(), self.operands[1], self.operands[0])
+                format!("{} (${:02X}{:02X})", mnemonic.to_uppercase(), self.operands[1], self.operands[0])
             }
             AddressingMode::IndirectX => {
-                format!("{} (${:02X},X)", self.instruction.to_uppercase(), self.operands[0])
+                format!("{} (${:02X},X)", mnemonic.to_uppercase(), self.operands[0])
             }
             AddressingMode::IndirectY => {
-                format!("{} (${:02X}),Y", self.instruction.to_uppercase(), self.operands[0])
+                format!("{} (${:02X}),Y", mnemonic.to_uppercase(), self.operands[0])
             }
             AddressingMode::Relative => {
-                format!("{} ${:02X}", self.instruction.to_uppercase(), self.address.wrapping_add(self.operands[0] as u16).wrapping_add(2))
+                format!("{} ${:04X}", mnemonic.to_uppercase(), self.address.wrapping_add(self.operands[0] as i8 as u16).wrapping_add(2))
             }
             AddressingMode::Accumulator => {
-                format!("{} A", self.instruction.to_uppercase())
+                format!("{} A", mnemonic.to_uppercase())
             }
             AddressingMode::Implied => {
-                self.instruction.to_string().to_uppercase()
+                mnemonic.to_uppercase()
+            }
+            AddressingMode::ZeroPageIndirect => {
+                format!("{} (${:02X})", mnemonic.to_uppercase(), self.operands[0])
+            }
+            AddressingMode::AbsoluteIndexedIndirect => {
+                format!("{} (${:02X}{:02X},X)", mnemonic.to_uppercase(), self.operands[1], self.operands[0])
             }
         })
     }
@@ -97,9 +112,13 @@ impl Display for Trace {
             write!(f, "   ")?;
         }
         if self.instruction.length > 2 {
-            write!(f, "{:02X}  ", self.instruction.operands[1])?;
+            write!(f, "{:02X} ", self.instruction.operands[1])?;
         } else {
-            write!(f, "    ")?;
+            write!(f, "   ")?;
+        }
+        // The marker of unofficial opcodes takes the place of the separating space
+        if !self.instruction.unofficial {
+            write!(f, " ")?;
         }
 
         let instruction = match self.instruction.addressing_mode {
@@ -115,13 +134,14 @@ impl Display for Trace {
                 format!("{} @ {:02X} = {:02X}", self.instruction, self.data_address, self.data_at_address as u8),
             AddressingMode::AbsoluteX | AddressingMode::AbsoluteY =>
                 format!("{} @ {:04X} = {:02X}", self.instruction, self.data_address, self.data_at_address as u8),
-            AddressingMode::Indirect => format!("{} = {:04X}", self.instruction, self.data_address),
+            AddressingMode::Indirect | AddressingMode::AbsoluteIndexedIndirect => format!("{} = {:04X}", self.instruction, self.data_address),
+            AddressingMode::ZeroPageIndirect => format!("{} = {:04X} = {:02X}", self.instruction, self.data_address, self.data_at_address as u8),
             AddressingMode::IndirectX => format!("{} @ {:02X} = {:04X} = {:02X}", self.instruction, self.register_x.wrapping_add(self.instruction.operands[0]), self.data_address, self.data_at_address as u8),
             AddressingMode::IndirectY => format!("{} = {:04X} @ {:04X} = {:02X}", self.instruction, self.data_address.wrapping_sub(self.register_y as u16), self.data_address, self.data_at_address as u8),
             _ => self.instruction.to_string(),
         };
-        write!(f, "{:<32}", instruction)?;
-        write!(f, "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}", self.register_a, self.register_x, self.register_y, self.status_flags.status, self.register_sp)
+        write!(f, "{:<width$}", instruction, width = 32 + self.instruction.unofficial as usize)?;
+        write!(f, "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}", self.register_a, self.register_x, self.register_y, self.status_flags.status, self.register_sp, self.cycles)
 
     }
 }
\ No newline at end of file
@@ -1,12 +1,27 @@
 mod operations;
+pub mod cycle;
 pub mod disassembly;
+pub mod opcodes;
+pub mod state;
+pub mod trap;
+#[cfg(test)]
+mod test;
 
+use crate::cpu::cycle::{PartialInstruction, Replay};
 use crate::cpu::disassembly::Trace;
+use crate::cpu::opcodes::{OpcodeInfo, CMOS_OPCODES, OPCODES};
 use crate::memory::Bus;
 use crate::EmulationError;
 
 const STACK_BASE: u16 = 0x0100;
 const STACK_RESET: u8 = 0xfd;
+// The reset sequence takes as long as an interrupt before the first instruction is fetched.
+const RESET_CYCLES: u64 = 7;
+const INTERRUPT_CYCLES: u8 = 7;
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum AddressingMode {
@@ -23,36 +38,122 @@ pub enum AddressingMode {
     Relative,
     Accumulator,
     Implied,
+    /// `($nn)`, only on the 65C02.
+    ZeroPageIndirect,
+    /// `($nnnn,X)`, only on the 65C02.
+    AbsoluteIndexedIndirect,
 }
 
-#[derive(Copy, Clone)]
+/// The member of the 6502 family being emulated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CpuVariant {
+    /// The NES' Ricoh 2A03, an NMOS 6502 whose decimal mode is disconnected.
+    #[default]
+    Nes2A03,
+    /// The original NMOS 6502, with decimal mode ADC and SBC.
+    Nmos6502,
+    /// The CMOS 65C02, with its extra instructions and addressing modes, no unofficial opcodes and
+    /// valid N and Z flags in decimal mode.
+    Cmos65C02,
+}
+
+impl CpuVariant {
+    pub fn has_decimal_mode(&self) -> bool {
+        !matches!(self, CpuVariant::Nes2A03)
+    }
+
+    /// The variant's instruction set, indexed by opcode.
+    pub fn opcodes(&self) -> &'static [Option<OpcodeInfo>; 256] {
+        match self {
+            CpuVariant::Cmos65C02 => &CMOS_OPCODES,
+            _ => &OPCODES,
+        }
+    }
+}
+
+/// Conditions under which the CPU halts on its own, besides emulation errors. The default never
+/// halts, which is what real games expect.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct HaltPolicy {
+    /// Halt on BRK instead of taking the interrupt, leaving PC on the BRK. The BRK never runs, so
+    /// it adds no cycles and isn't counted as an instruction.
+    pub on_brk: bool,
+    /// Halt on the JAM (KIL) opcodes, leaving PC on the JAM.
+    pub on_jam: bool,
+    /// Halt after an instruction that jumps or branches to itself, such as `JMP *` or `BNE *`.
+    pub on_self_jump: bool,
+    /// Halt once the cycle count reaches this value.
+    pub max_cycles: Option<u64>,
+    /// Halt once this many instructions have been executed.
+    pub max_instructions: Option<u64>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct CpuStatus {
     pub status: u8,
 }
 
 
-pub struct Cpu {
+/// A 6502 family CPU driving the bus `B`. Generic so bus accesses can be inlined; `BoxedCpu` takes
+/// any bus at the cost of dynamic dispatch.
+pub struct Cpu<B: Bus> {
     pub register_a: u8,
     pub register_x: u8,
     pub register_y: u8,
     pub register_sp: u8,
     pub register_pc: u16,
     pub status_flags: CpuStatus,
-    pub bus: Box<dyn Bus + Send + Sync>,
+    pub variant: CpuVariant,
+    pub bus: B,
     pub halted: bool,
+    pub halt_policy: HaltPolicy,
+    pub cycles: u64,
+    pub instructions: u64,
+    /// When disabled, unofficial opcodes are rejected as invalid, as a strictness check for homebrew.
+    pub allow_unofficial_opcodes: bool,
+    nmi_line: bool,
+    nmi_pending: bool,
+    irq_line: bool,
+    // CLI, SEI and PLP change the I flag after interrupts are polled, so the next poll still
+    // sees the value from before them.
+    delayed_interrupt_flag: Option<bool>,
+    partial_instruction: Option<PartialInstruction>,
+    replay: Option<Replay>,
+}
+
+/// An instruction or interrupt sequence that ran to completion. An instruction that halted
+/// instead of running takes no cycles.
+#[derive(Copy, Clone)]
+struct Execution {
+    address: u16,
+    cycles: u8,
+    interrupt: bool,
 }
 
-impl Cpu {
-    pub fn new(bus: Box<dyn Bus + Send + Sync>) -> Cpu {
+pub type BoxedCpu = Cpu<Box<dyn Bus + Send + Sync>>;
+
+impl<B: Bus> Cpu<B> {
+    pub fn new(mut bus: B) -> Cpu<B> {
         Cpu {
             register_a: 0x00,
             register_x: 0x00,
             register_y: 0x00,
             register_sp: STACK_RESET,
-            register_pc: bus.read_word(0xFFFC).unwrap(),
+            register_pc: bus.read_word(RESET_VECTOR).unwrap(),
             status_flags: CpuStatus::new(),
+            variant: CpuVariant::default(),
             bus,
             halted: false,
+            halt_policy: HaltPolicy::default(),
+            cycles: RESET_CYCLES,
+            instructions: 0,
+            allow_unofficial_opcodes: true,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: false,
+            delayed_interrupt_flag: None,
+            partial_instruction: None,
+            replay: None,
         }
     }
 
@@ -61,32 +162,91 @@ impl Cpu {
         self.register_x = 0x00;
         self.register_y = 0x00;
         self.register_sp = STACK_RESET;
-        self.register_pc = self.bus.read_word(0xFFFC).unwrap();
+        self.register_pc = self.bus.read_word(RESET_VECTOR).unwrap();
         self.status_flags.reset();
         self.halted = false;
+        self.cycles = RESET_CYCLES;
+        self.instructions = 0;
+        self.nmi_line = false;
+        self.nmi_pending = false;
+        self.irq_line = false;
+        self.delayed_interrupt_flag = None;
+        self.partial_instruction = None;
         self.bus.reset();
     }
 
-    pub fn step(&mut self) -> Result<bool, EmulationError> {
-        if !self.halted {
-            let opcode = self.bus.read(self.register_pc)?;
-            self.register_pc = self.register_pc.wrapping_add(1);
+    /// Drives the NMI line. NMIs are edge triggered: one is raised each time the line goes from
+    /// inactive to active, and it is serviced before the next instruction.
+    pub fn set_nmi_line(&mut self, active: bool) {
+        if active && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = active;
+    }
+
+    /// Drives the IRQ line. IRQs are level triggered: one is serviced before every instruction
+    /// while the line is active and the interrupt disable flag is clear.
+    pub fn set_irq_line(&mut self, active: bool) {
+        self.irq_line = active;
+    }
 
-            if let Err(e) = self.handle_opcode(opcode) {
+    /// Executes a single instruction, returning the number of cycles it took. The running total
+    /// is kept in `cycles`. If an instruction was started with `tick`, it is finished instead.
+    pub fn step(&mut self) -> Result<u8, EmulationError> {
+        if self.halted {
+            return Err(EmulationError::Halted);
+        }
+        if self.mid_instruction() {
+            return self.finish_instruction();
+        }
+
+        match self.execute() {
+            Ok(execution) => {
+                self.cycles += execution.cycles as u64;
+                self.complete(&execution);
+                Ok(execution.cycles)
+            }
+            Err(e) => {
                 self.halted = true;
-                return Err(e);
+                Err(e)
             }
-            Ok(self.halted)
-        } else {
-            Err(EmulationError::Halted)
         }
     }
 
+    /// Runs the next interrupt sequence or instruction without accounting for its cycles.
+    fn execute(&mut self) -> Result<Execution, EmulationError> {
+        let interrupt_disabled = self.delayed_interrupt_flag.take()
+            .unwrap_or_else(|| self.status_flags.get_interrupt());
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            return self.service_interrupt(NMI_VECTOR);
+        }
+        if self.irq_line && !interrupt_disabled {
+            return self.service_interrupt(IRQ_VECTOR);
+        }
+
+        let address = self.register_pc;
+        let opcode = self.fetch_opcode(address)?;
+        self.register_pc = self.register_pc.wrapping_add(1);
+        let cycles = self.handle_opcode(opcode)?;
+        Ok(Execution { address, cycles, interrupt: false })
+    }
+
+    fn complete(&mut self, execution: &Execution) {
+        if !execution.interrupt && execution.cycles > 0 {
+            self.instructions += 1;
+            if self.halt_policy.on_self_jump && self.register_pc == execution.address {
+                self.halt();
+            }
+        }
+        self.apply_halt_limits();
+    }
+
     pub fn trace(&self) -> Trace {
         let instruction = self.disassemble(self.register_pc, [
-            self.bus.read(self.register_pc).unwrap_or(0),
-            self.bus.read(self.register_pc.wrapping_add(1)).unwrap_or(0),
-            self.bus.read(self.register_pc.wrapping_add(2)).unwrap_or(0),
+            self.bus.peek(self.register_pc).unwrap_or(0),
+            self.bus.peek(self.register_pc.wrapping_add(1)).unwrap_or(0),
+            self.bus.peek(self.register_pc.wrapping_add(2)).unwrap_or(0),
         ]).unwrap_or_default();
         let addressing_mode = instruction.addressing_mode;
 
@@ -98,11 +258,28 @@ impl Cpu {
             register_y: self.register_y,
             register_sp: self.register_sp,
             register_pc: self.register_pc,
-            data_at_x: self.bus.read_word(self.register_x as u16).unwrap_or(0),
-            data_at_y: self.bus.read_word(self.register_x as u16).unwrap_or(0),
+            data_at_x: self.bus.peek_word(self.register_x as u16).unwrap_or(0),
+            data_at_y: self.bus.peek_word(self.register_x as u16).unwrap_or(0),
             data_address: self.get_operand_address(addressing_mode, self.register_pc.wrapping_add(1)).unwrap_or(0),
-            data_at_address: self.bus.read_word(self.get_operand_address(addressing_mode, self.register_pc.wrapping_add(1)).unwrap_or(0)).unwrap_or(0),
+            data_at_address: self.bus.peek_word(self.get_operand_address(addressing_mode, self.register_pc.wrapping_add(1)).unwrap_or(0)).unwrap_or(0),
             status_flags: self.status_flags,
+            cycles: self.cycles,
+        }
+    }
+
+    fn service_interrupt(&mut self, vector: u16) -> Result<Execution, EmulationError> {
+        let address = self.register_pc;
+        // The opcode that would have run is fetched twice and discarded
+        self.dummy_read(address)?;
+        self.dummy_read(address)?;
+        self.interrupt(vector, false)?;
+        Ok(Execution { address, cycles: INTERRUPT_CYCLES, interrupt: true })
+    }
+
+    fn apply_halt_limits(&mut self) {
+        if self.halt_policy.max_cycles.is_some_and(|max| self.cycles >= max)
+            || self.halt_policy.max_instructions.is_some_and(|max| self.instructions >= max) {
+            self.halt();
         }
     }
 
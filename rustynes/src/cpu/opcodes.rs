@@ -0,0 +1,157 @@
+use emulator_macros::opcode_table;
+use crate::cpu::AddressingMode;
+
+/// What an opcode does, as listed in the instruction set tables.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    pub addressing_mode: AddressingMode,
+    /// Length in bytes, including the opcode.
+    pub length: u8,
+    /// Cycles taken before page crossing, branch and decimal mode penalties.
+    pub cycles: u8,
+    pub unofficial: bool,
+}
+
+/// Passes the NMOS instruction set to `$callback` after `$args`, so dispatch, disassembly and
+/// the opcode tables share a single definition. Instructions prefixed with `*` are unofficial.
+macro_rules! nmos_instructions {
+    ($callback:ident!($($args:tt)*) $(else $($fallback:tt)+)?) => {
+        $callback!(
+            $($args)* {
+                adc: 0x69 => Immediate (2) [2], 0x65 => ZeroPage (2) [3], 0x75 => ZeroPageX (2) [4], 0x6D => Absolute (3) [4], 0x7D => AbsoluteX (3) [4], 0x79 => AbsoluteY (3) [4], 0x61 => IndirectX (2) [6], 0x71 => IndirectY (2) [5];
+                and: 0x29 => Immediate (2) [2], 0x25 => ZeroPage (2) [3], 0x35 => ZeroPageX (2) [4], 0x2D => Absolute (3) [4], 0x3D => AbsoluteX (3) [4], 0x39 => AbsoluteY (3) [4], 0x21 => IndirectX (2) [6], 0x31 => IndirectY (2) [5];
+                asl: 0x0A => Accumulator (1) [2], 0x06 => ZeroPage (2) [5], 0x16 => ZeroPageX (2) [6], 0x0E => Absolute (3) [6], 0x1E => AbsoluteX (3) [7];
+                bcc: 0x90 => Relative (2) [2];
+                bcs: 0xB0 => Relative (2) [2];
+                beq: 0xF0 => Relative (2) [2];
+                bit: 0x24 => ZeroPage (2) [3], 0x2C => Absolute (3) [4];
+                bmi: 0x30 => Relative (2) [2];
+                bne: 0xD0 => Relative (2) [2];
+                bpl: 0x10 => Relative (2) [2];
+                brk: 0x00 => Implied (1) [7];
+                bvc: 0x50 => Relative (2) [2];
+                bvs: 0x70 => Relative (2) [2];
+                clc: 0x18 => Implied (1) [2];
+                cld: 0xD8 => Implied (1) [2];
+                cli: 0x58 => Implied (1) [2];
+                clv: 0xB8 => Implied (1) [2];
+                cmp: 0xC9 => Immediate (2) [2], 0xC5 => ZeroPage (2) [3], 0xD5 => ZeroPageX (2) [4], 0xCD => Absolute (3) [4], 0xDD => AbsoluteX (3) [4], 0xD9 => AbsoluteY (3) [4], 0xC1 => IndirectX (2) [6], 0xD1 => IndirectY (2) [5];
+                cpx: 0xE0 => Immediate (2) [2], 0xE4 => ZeroPage (2) [3], 0xEC => Absolute (3) [4];
+                cpy: 0xC0 => Immediate (2) [2], 0xC4 => ZeroPage (2) [3], 0xCC => Absolute (3) [4];
+                dec: 0xC6 => ZeroPage (2) [5], 0xD6 => ZeroPageX (2) [6], 0xCE => Absolute (3) [6], 0xDE => AbsoluteX (3) [7];
+                dex: 0xCA => Implied (1) [2];
+                dey: 0x88 => Implied (1) [2];
+                eor: 0x49 => Immediate (2) [2], 0x45 => ZeroPage (2) [3], 0x55 => ZeroPageX (2) [4], 0x4D => Absolute (3) [4], 0x5D => AbsoluteX (3) [4], 0x59 => AbsoluteY (3) [4], 0x41 => IndirectX (2) [6], 0x51 => IndirectY (2) [5];
+                inc: 0xE6 => ZeroPage (2) [5], 0xF6 => ZeroPageX (2) [6], 0xEE => Absolute (3) [6], 0xFE => AbsoluteX (3) [7];
+                inx: 0xE8 => Implied (1) [2];
+                iny: 0xC8 => Implied (1) [2];
+                jmp: 0x4C => Absolute (3) [3], 0x6C => Indirect (3) [5];
+                jsr: 0x20 => Absolute (3) [6];
+                lda: 0xA9 => Immediate (2) [2], 0xA5 => ZeroPage (2) [3], 0xB5 => ZeroPageX (2) [4], 0xAD => Absolute (3) [4], 0xBD => AbsoluteX (3) [4], 0xB9 => AbsoluteY (3) [4], 0xA1 => IndirectX (2) [6], 0xB1 => IndirectY (2) [5];
+                ldx: 0xA2 => Immediate (2) [2], 0xA6 => ZeroPage (2) [3], 0xB6 => ZeroPageY (2) [4], 0xAE => Absolute (3) [4], 0xBE => AbsoluteY (3) [4];
+                ldy: 0xA0 => Immediate (2) [2], 0xA4 => ZeroPage (2) [3], 0xB4 => ZeroPageX (2) [4], 0xAC => Absolute (3) [4], 0xBC => AbsoluteX (3) [4];
+                lsr: 0x4A => Accumulator (1) [2], 0x46 => ZeroPage (2) [5], 0x56 => ZeroPageX (2) [6], 0x4E => Absolute (3) [6], 0x5E => AbsoluteX (3) [7];
+                nop: 0xEA => Implied (1) [2];
+                ora: 0x09 => Immediate (2) [2], 0x05 => ZeroPage (2) [3], 0x15 => ZeroPageX (2) [4], 0x0D => Absolute (3) [4], 0x1D => AbsoluteX (3) [4], 0x19 => AbsoluteY (3) [4], 0x01 => IndirectX (2) [6], 0x11 => IndirectY (2) [5];
+                pha: 0x48 => Implied (1) [3];
+                php: 0x08 => Implied (1) [3];
+                pla: 0x68 => Implied (1) [4];
+                plp: 0x28 => Implied (1) [4];
+                rol: 0x2A => Accumulator (1) [2], 0x26 => ZeroPage (2) [5], 0x36 => ZeroPageX (2) [6], 0x2E => Absolute (3) [6], 0x3E => AbsoluteX (3) [7];
+                ror: 0x6A => Accumulator (1) [2], 0x66 => ZeroPage (2) [5], 0x76 => ZeroPageX (2) [6], 0x6E => Absolute (3) [6], 0x7E => AbsoluteX (3) [7];
+                rti: 0x40 => Implied (1) [6];
+                rts: 0x60 => Implied (1) [6];
+                sbc: 0xE9 => Immediate (2) [2], 0xE5 => ZeroPage (2) [3], 0xF5 => ZeroPageX (2) [4], 0xED => Absolute (3) [4], 0xFD => AbsoluteX (3) [4], 0xF9 => AbsoluteY (3) [4], 0xE1 => IndirectX (2) [6], 0xF1 => IndirectY (2) [5];
+                sec: 0x38 => Implied (1) [2];
+                sed: 0xF8 => Implied (1) [2];
+                sei: 0x78 => Implied (1) [2];
+                sta: 0x85 => ZeroPage (2) [3], 0x95 => ZeroPageX (2) [4], 0x8D => Absolute (3) [4], 0x9D => AbsoluteX (3) [5], 0x99 => AbsoluteY (3) [5], 0x81 => IndirectX (2) [6], 0x91 => IndirectY (2) [6];
+                stx: 0x86 => ZeroPage (2) [3], 0x96 => ZeroPageY (2) [4], 0x8E => Absolute (3) [4];
+                sty: 0x84 => ZeroPage (2) [3], 0x94 => ZeroPageX (2) [4], 0x8C => Absolute (3) [4];
+                tax: 0xAA => Implied (1) [2];
+                tay: 0xA8 => Implied (1) [2];
+                tsx: 0xBA => Implied (1) [2];
+                txa: 0x8A => Implied (1) [2];
+                txs: 0x9A => Implied (1) [2];
+                tya: 0x98 => Implied (1) [2];
+                *alr: 0x4B => Immediate (2) [2];
+                *anc: 0x0B => Immediate (2) [2], 0x2B => Immediate (2) [2];
+                *arr: 0x6B => Immediate (2) [2];
+                *axs: 0xCB => Immediate (2) [2];
+                *dcp: 0xC7 => ZeroPage (2) [5], 0xD7 => ZeroPageX (2) [6], 0xCF => Absolute (3) [6], 0xDF => AbsoluteX (3) [7], 0xDB => AbsoluteY (3) [7], 0xC3 => IndirectX (2) [8], 0xD3 => IndirectY (2) [8];
+                *isb: 0xE7 => ZeroPage (2) [5], 0xF7 => ZeroPageX (2) [6], 0xEF => Absolute (3) [6], 0xFF => AbsoluteX (3) [7], 0xFB => AbsoluteY (3) [7], 0xE3 => IndirectX (2) [8], 0xF3 => IndirectY (2) [8];
+                *jam: 0x02 => Implied (1) [2], 0x12 => Implied (1) [2], 0x22 => Implied (1) [2], 0x32 => Implied (1) [2], 0x42 => Implied (1) [2], 0x52 => Implied (1) [2],
+                        0x62 => Implied (1) [2], 0x72 => Implied (1) [2], 0x92 => Implied (1) [2], 0xB2 => Implied (1) [2], 0xD2 => Implied (1) [2], 0xF2 => Implied (1) [2];
+                *lax: 0xA7 => ZeroPage (2) [3], 0xB7 => ZeroPageY (2) [4], 0xAF => Absolute (3) [4], 0xBF => AbsoluteY (3) [4], 0xA3 => IndirectX (2) [6], 0xB3 => IndirectY (2) [5];
+                *nop: 0x1A => Implied (1) [2], 0x3A => Implied (1) [2], 0x5A => Implied (1) [2], 0x7A => Implied (1) [2], 0xDA => Implied (1) [2], 0xFA => Implied (1) [2],
+                        0x80 => Immediate (2) [2], 0x82 => Immediate (2) [2], 0x89 => Immediate (2) [2], 0xC2 => Immediate (2) [2], 0xE2 => Immediate (2) [2],
+                        0x04 => ZeroPage (2) [3], 0x44 => ZeroPage (2) [3], 0x64 => ZeroPage (2) [3],
+                        0x14 => ZeroPageX (2) [4], 0x34 => ZeroPageX (2) [4], 0x54 => ZeroPageX (2) [4], 0x74 => ZeroPageX (2) [4], 0xD4 => ZeroPageX (2) [4], 0xF4 => ZeroPageX (2) [4],
+                        0x0C => Absolute (3) [4],
+                        0x1C => AbsoluteX (3) [4], 0x3C => AbsoluteX (3) [4], 0x5C => AbsoluteX (3) [4], 0x7C => AbsoluteX (3) [4], 0xDC => AbsoluteX (3) [4], 0xFC => AbsoluteX (3) [4];
+                *rla: 0x27 => ZeroPage (2) [5], 0x37 => ZeroPageX (2) [6], 0x2F => Absolute (3) [6], 0x3F => AbsoluteX (3) [7], 0x3B => AbsoluteY (3) [7], 0x23 => IndirectX (2) [8], 0x33 => IndirectY (2) [8];
+                *rra: 0x67 => ZeroPage (2) [5], 0x77 => ZeroPageX (2) [6], 0x6F => Absolute (3) [6], 0x7F => AbsoluteX (3) [7], 0x7B => AbsoluteY (3) [7], 0x63 => IndirectX (2) [8], 0x73 => IndirectY (2) [8];
+                *sax: 0x87 => ZeroPage (2) [3], 0x97 => ZeroPageY (2) [4], 0x8F => Absolute (3) [4], 0x83 => IndirectX (2) [6];
+                *sbc: 0xEB => Immediate (2) [2];
+                *slo: 0x07 => ZeroPage (2) [5], 0x17 => ZeroPageX (2) [6], 0x0F => Absolute (3) [6], 0x1F => AbsoluteX (3) [7], 0x1B => AbsoluteY (3) [7], 0x03 => IndirectX (2) [8], 0x13 => IndirectY (2) [8];
+                *sre: 0x47 => ZeroPage (2) [5], 0x57 => ZeroPageX (2) [6], 0x4F => Absolute (3) [6], 0x5F => AbsoluteX (3) [7], 0x5B => AbsoluteY (3) [7], 0x43 => IndirectX (2) [8], 0x53 => IndirectY (2) [8];
+            } $(else $($fallback)+)?
+        )
+    };
+}
+pub(super) use nmos_instructions;
+
+/// Passes the 65C02 instruction set to `$callback` like `nmos_instructions`. It only lists what
+/// differs from the NMOS instruction set, so it is meant to fall back on it.
+macro_rules! cmos_instructions {
+    ($callback:ident!($($args:tt)*) $(else $($fallback:tt)+)?) => {
+        $callback!(
+            $($args)* {
+                adc: 0x72 => ZeroPageIndirect (2) [5];
+                and: 0x32 => ZeroPageIndirect (2) [5];
+                asl: 0x1E => AbsoluteX (3) [6];
+                bit: 0x89 => Immediate (2) [2], 0x34 => ZeroPageX (2) [4], 0x3C => AbsoluteX (3) [4];
+                bra: 0x80 => Relative (2) [2];
+                cmp: 0xD2 => ZeroPageIndirect (2) [5];
+                dec: 0x3A => Accumulator (1) [2];
+                eor: 0x52 => ZeroPageIndirect (2) [5];
+                inc: 0x1A => Accumulator (1) [2];
+                jmp: 0x6C => Indirect (3) [6], 0x7C => AbsoluteIndexedIndirect (3) [6];
+                lda: 0xB2 => ZeroPageIndirect (2) [5];
+                lsr: 0x5E => AbsoluteX (3) [6];
+                nop: 0x02 => Immediate (2) [2], 0x22 => Immediate (2) [2], 0x42 => Immediate (2) [2], 0x62 => Immediate (2) [2], 0x82 => Immediate (2) [2], 0xC2 => Immediate (2) [2], 0xE2 => Immediate (2) [2],
+                        0x44 => ZeroPage (2) [3], 0x54 => ZeroPageX (2) [4], 0xD4 => ZeroPageX (2) [4], 0xF4 => ZeroPageX (2) [4],
+                        0x5C => Absolute (3) [8], 0xDC => Absolute (3) [4], 0xFC => Absolute (3) [4],
+                        0x03 => Implied (1) [1], 0x07 => Implied (1) [1], 0x0B => Implied (1) [1], 0x0F => Implied (1) [1], 0x13 => Implied (1) [1], 0x17 => Implied (1) [1], 0x1B => Implied (1) [1], 0x1F => Implied (1) [1],
+                        0x23 => Implied (1) [1], 0x27 => Implied (1) [1], 0x2B => Implied (1) [1], 0x2F => Implied (1) [1], 0x33 => Implied (1) [1], 0x37 => Implied (1) [1], 0x3B => Implied (1) [1], 0x3F => Implied (1) [1],
+                        0x43 => Implied (1) [1], 0x47 => Implied (1) [1], 0x4B => Implied (1) [1], 0x4F => Implied (1) [1], 0x53 => Implied (1) [1], 0x57 => Implied (1) [1], 0x5B => Implied (1) [1], 0x5F => Implied (1) [1],
+                        0x63 => Implied (1) [1], 0x67 => Implied (1) [1], 0x6B => Implied (1) [1], 0x6F => Implied (1) [1], 0x73 => Implied (1) [1], 0x77 => Implied (1) [1], 0x7B => Implied (1) [1], 0x7F => Implied (1) [1],
+                        0x83 => Implied (1) [1], 0x87 => Implied (1) [1], 0x8B => Implied (1) [1], 0x8F => Implied (1) [1], 0x93 => Implied (1) [1], 0x97 => Implied (1) [1], 0x9B => Implied (1) [1], 0x9F => Implied (1) [1],
+                        0xA3 => Implied (1) [1], 0xA7 => Implied (1) [1], 0xAB => Implied (1) [1], 0xAF => Implied (1) [1], 0xB3 => Implied (1) [1], 0xB7 => Implied (1) [1], 0xBB => Implied (1) [1], 0xBF => Implied (1) [1],
+                        0xC3 => Implied (1) [1], 0xC7 => Implied (1) [1], 0xCB => Implied (1) [1], 0xCF => Implied (1) [1], 0xD3 => Implied (1) [1], 0xD7 => Implied (1) [1], 0xDB => Implied (1) [1], 0xDF => Implied (1) [1],
+                        0xE3 => Implied (1) [1], 0xE7 => Implied (1) [1], 0xEB => Implied (1) [1], 0xEF => Implied (1) [1], 0xF3 => Implied (1) [1], 0xF7 => Implied (1) [1], 0xFB => Implied (1) [1], 0xFF => Implied (1) [1];
+                ora: 0x12 => ZeroPageIndirect (2) [5];
+                phx: 0xDA => Implied (1) [3];
+                phy: 0x5A => Implied (1) [3];
+                plx: 0xFA => Implied (1) [4];
+                ply: 0x7A => Implied (1) [4];
+                rol: 0x3E => AbsoluteX (3) [6];
+                ror: 0x7E => AbsoluteX (3) [6];
+                sbc: 0xF2 => ZeroPageIndirect (2) [5];
+                sta: 0x92 => ZeroPageIndirect (2) [5];
+                stz: 0x64 => ZeroPage (2) [3], 0x74 => ZeroPageX (2) [4], 0x9C => Absolute (3) [4], 0x9E => AbsoluteX (3) [5];
+                trb: 0x14 => ZeroPage (2) [5], 0x1C => Absolute (3) [6];
+                tsb: 0x04 => ZeroPage (2) [5], 0x0C => Absolute (3) [6];
+            } $(else $($fallback)+)?
+        )
+    };
+}
+pub(super) use cmos_instructions;
+
+/// The NMOS 6502 and 2A03 instruction set, indexed by opcode. The unstable opcodes that aren't
+/// emulated are `None`.
+pub const OPCODES: [Option<OpcodeInfo>; 256] = nmos_instructions!(opcode_table!());
+
+/// The 65C02 instruction set, indexed by opcode.
+pub const CMOS_OPCODES: [Option<OpcodeInfo>; 256] = cmos_instructions!(opcode_table!() else OPCODES);
@@ -1,8 +1,17 @@
 use emulator_macros::{disassemble_op, call_op};
-use crate::cpu::{AddressingMode, Cpu, STACK_BASE};
+use crate::cpu::{AddressingMode, Cpu, CpuVariant, IRQ_VECTOR, NMI_VECTOR, STACK_BASE};
 use crate::cpu::disassembly::Instruction;
+use crate::cpu::opcodes::{cmos_instructions, nmos_instructions};
+use crate::memory::Bus;
 use crate::EmulationError;
 
+#[derive(Copy, Clone, PartialEq)]
+enum Access {
+    Read,
+    /// Writes, including read-modify-writes, always spend the cycle fixing up indexed addresses.
+    Write,
+}
+
 struct OpResult {
     extra_cycles: u8,
     increment_pc: bool,
@@ -17,195 +26,224 @@ impl OpResult {
     }
 }
 
-impl Cpu {
+// `Cpu::tick` runs operations again from the start of the instruction on every cycle, stopping
+// them at the first access it hasn't made yet. So operations may only change the registers, flags
+// and interrupt state it puts back between runs, and must reach the bus through `read`, `write`
+// and the dummy accesses, never through `self.bus`.
+impl<B: Bus> Cpu<B> {
+    /// Runs the instruction whose opcode was just fetched, returning the cycles it took. A BRK
+    /// the halt policy stops on never runs, so it takes none.
     pub(super) fn handle_opcode(&mut self, opcode: u8) -> Result<u8, EmulationError> {
-        call_op!(
-            opcode {
-                adc: 0x69 => Immediate (2), 0x65 => ZeroPage (2), 0x75 => ZeroPageX (2), 0x6D => Absolute (3), 0x7D => AbsoluteX (3), 0x79 => AbsoluteY (3), 0x61 => IndirectX (2), 0x71 => IndirectY (2);
-                and: 0x29 => Immediate (2), 0x25 => ZeroPage (2), 0x35 => ZeroPageX (2), 0x2D => Absolute (3), 0x3D => AbsoluteX (3), 0x39 => AbsoluteY (3), 0x21 => IndirectX (2), 0x31 => IndirectY (2);
-                asl: 0x0A => Accumulator (1), 0x06 => ZeroPage (2), 0x16 => ZeroPageX (2), 0x0E => Absolute (3), 0x1E => AbsoluteX (3);
-                bcc: 0x90 => Relative (2);
-                bcs: 0xB0 => Relative (2);
-                beq: 0xF0 => Relative (2);
-                bit: 0x24 => ZeroPage (2), 0x2C => Absolute (3);
-                bmi: 0x30 => Relative (2);
-                bne: 0xD0 => Relative (2);
-                bpl: 0x10 => Relative (2);
-                brk: 0x00 => Implied (1);
-                bvc: 0x50 => Relative (2);
-                bvs: 0x70 => Relative (2);
-                clc: 0x18 => Implied (1);
-                cld: 0xD8 => Implied (1);
-                cli: 0x58 => Implied (1);
-                clv: 0xB8 => Implied (1);
-                cmp: 0xC9 => Immediate (2), 0xC5 => ZeroPage (2), 0xD5 => ZeroPageX (2), 0xCD => Absolute (3), 0xDD => AbsoluteX (3), 0xD9 => AbsoluteY (3), 0xC1 => IndirectX (2), 0xD1 => IndirectY (2);
-                cpx: 0xE0 => Immediate (2), 0xE4 => ZeroPage (2), 0xEC => Absolute (3);
-                cpy: 0xC0 => Immediate (2), 0xC4 => ZeroPage (2), 0xCC => Absolute (3);
-                dec: 0xC6 => ZeroPage (2), 0xD6 => ZeroPageX (2), 0xCE => Absolute (3), 0xDE => AbsoluteX (3);
-                dex: 0xCA => Implied (1);
-                dey: 0x88 => Implied (1);
-                eor: 0x49 => Immediate (2), 0x45 => ZeroPage (2), 0x55 => ZeroPageX (2), 0x4D => Absolute (3), 0x5D => AbsoluteX (3), 0x59 => AbsoluteY (3), 0x41 => IndirectX (2), 0x51 => IndirectY (2);
-                inc: 0xE6 => ZeroPage (2), 0xF6 => ZeroPageX (2), 0xEE => Absolute (3), 0xFE => AbsoluteX (3);
-                inx: 0xE8 => Implied (1);
-                iny: 0xC8 => Implied (1);
-                jmp: 0x4C => Absolute (3), 0x6C => Indirect  (3);
-                jsr: 0x20 => Absolute (3);
-                lda: 0xA9 => Immediate (2), 0xA5 => ZeroPage (2), 0xB5 => ZeroPageX (2), 0xAD => Absolute (3), 0xBD => AbsoluteX (3), 0xB9 => AbsoluteY (3), 0xA1 => IndirectX (2), 0xB1 => IndirectY (2);
-                ldx: 0xA2 => Immediate (2), 0xA6 => ZeroPage (2), 0xB6 => ZeroPageY (2), 0xAE => Absolute (3), 0xBE => AbsoluteY (3);
-                ldy: 0xA0 => Immediate (2), 0xA4 => ZeroPage (2), 0xB4 => ZeroPageX (2), 0xAC => Absolute (3), 0xBC => AbsoluteX (3);
-                lsr: 0x4A => Accumulator (1), 0x46 => ZeroPage (2), 0x56 => ZeroPageX (2), 0x4E => Absolute (3), 0x5E => AbsoluteX (3);
-                nop: 0xEA => Implied (1);
-                ora: 0x09 => Immediate (2), 0x05 => ZeroPage (2), 0x15 => ZeroPageX (2), 0x0D => Absolute (3), 0x1D => AbsoluteX (3), 0x19 => AbsoluteY (3), 0x01 => IndirectX (2), 0x11 => IndirectY (2);
-                pha: 0x48 => Implied (1);
-                php: 0x08 => Implied (1);
-                pla: 0x68 => Implied (1);
-                plp: 0x28 => Implied (1);
-                rol: 0x2A => Accumulator (1), 0x26 => ZeroPage (2), 0x36 => ZeroPageX (2), 0x2E => Absolute (3), 0x3E => AbsoluteX (3);
-                ror: 0x6A => Accumulator (1), 0x66 => ZeroPage (2), 0x76 => ZeroPageX (2), 0x6E => Absolute (3), 0x7E => AbsoluteX (3);
-                rti: 0x40 => Implied (1);
-                rts: 0x60 => Implied (1);
-                sbc: 0xE9 => Immediate (2), 0xE5 => ZeroPage (2), 0xF5 => ZeroPageX (2), 0xED => Absolute (3), 0xFD => AbsoluteX (3), 0xF9 => AbsoluteY (3), 0xE1 => IndirectX (2), 0xF1 => IndirectY (2);
-                sec: 0x38 => Implied (1);
-                sed: 0xF8 => Implied (1);
-                sei: 0x78 => Implied (1);
-                sta: 0x85 => ZeroPage (2), 0x95 => ZeroPageX (2), 0x8D => Absolute (3), 0x9D => AbsoluteX (3), 0x99 => AbsoluteY (3), 0x81 => IndirectX (2), 0x91 => IndirectY (2);
-                stx: 0x86 => ZeroPage (2), 0x96 => ZeroPageY (2), 0x8E => Absolute (3);
-                sty: 0x84 => ZeroPage (2), 0x94 => ZeroPageX (2), 0x8C => Absolute (3);
-                tax: 0xAA => Implied (1);
-                tay: 0xA8 => Implied (1);
-                tsx: 0xBA => Implied (1);
-                txa: 0x8A => Implied (1);
-                txs: 0x9A => Implied (1);
-                tya: 0x98 => Implied (1);
-            }
-        )
+        if opcode == 0x00 && self.halt_policy.on_brk {
+            self.register_pc = self.register_pc.wrapping_sub(1);
+            self.halt();
+            return Ok(0);
+        }
+        match self.variant {
+            CpuVariant::Cmos65C02 => self.handle_cmos_opcode(opcode),
+            _ => self.handle_nmos_opcode(opcode),
+        }
     }
 
     pub fn disassemble(&self, operation_address: u16, opcode_and_operands: [u8; 3]) -> Result<Instruction, EmulationError> {
-        disassemble_op!(
-            operation_address, opcode_and_operands {
-                adc: 0x69 => Immediate (2), 0x65 => ZeroPage (2), 0x75 => ZeroPageX (2), 0x6D => Absolute (3), 0x7D => AbsoluteX (3), 0x79 => AbsoluteY (3), 0x61 => IndirectX (2), 0x71 => IndirectY (2);
-                and: 0x29 => Immediate (2), 0x25 => ZeroPage (2), 0x35 => ZeroPageX (2), 0x2D => Absolute (3), 0x3D => AbsoluteX (3), 0x39 => AbsoluteY (3), 0x21 => IndirectX (2), 0x31 => IndirectY (2);
-                asl: 0x0A => Accumulator (1), 0x06 => ZeroPage (2), 0x16 => ZeroPageX (2), 0x0E => Absolute (3), 0x1E => AbsoluteX (3);
-                bcc: 0x90 => Relative (2);
-                bcs: 0xB0 => Relative (2);
-                beq: 0xF0 => Relative (2);
-                bit: 0x24 => ZeroPage (2), 0x2C => Absolute (3);
-                bmi: 0x30 => Relative (2);
-                bne: 0xD0 => Relative (2);
-                bpl: 0x10 => Relative (2);
-                brk: 0x00 => Implied (1);
-                bvc: 0x50 => Relative (2);
-                bvs: 0x70 => Relative (2);
-                clc: 0x18 => Implied (1);
-                cld: 0xD8 => Implied (1);
-                cli: 0x58 => Implied (1);
-                clv: 0xB8 => Implied (1);
-                cmp: 0xC9 => Immediate (2), 0xC5 => ZeroPage (2), 0xD5 => ZeroPageX (2), 0xCD => Absolute (3), 0xDD => AbsoluteX (3), 0xD9 => AbsoluteY (3), 0xC1 => IndirectX (2), 0xD1 => IndirectY (2);
-                cpx: 0xE0 => Immediate (2), 0xE4 => ZeroPage (2), 0xEC => Absolute (3);
-                cpy: 0xC0 => Immediate (2), 0xC4 => ZeroPage (2), 0xCC => Absolute (3);
-                dec: 0xC6 => ZeroPage (2), 0xD6 => ZeroPageX (2), 0xCE => Absolute (3), 0xDE => AbsoluteX (3);
-                dex: 0xCA => Implied (1);
-                dey: 0x88 => Implied (1);
-                eor: 0x49 => Immediate (2), 0x45 => ZeroPage (2), 0x55 => ZeroPageX (2), 0x4D => Absolute (3), 0x5D => AbsoluteX (3), 0x59 => AbsoluteY (3), 0x41 => IndirectX (2), 0x51 => IndirectY (2);
-                inc: 0xE6 => ZeroPage (2), 0xF6 => ZeroPageX (2), 0xEE => Absolute (3), 0xFE => AbsoluteX (3);
-                inx: 0xE8 => Implied (1);
-                iny: 0xC8 => Implied (1);
-                jmp: 0x4C => Absolute (3), 0x6C => Indirect  (3);
-                jsr: 0x20 => Absolute (3);
-                lda: 0xA9 => Immediate (2), 0xA5 => ZeroPage (2), 0xB5 => ZeroPageX (2), 0xAD => Absolute (3), 0xBD => AbsoluteX (3), 0xB9 => AbsoluteY (3), 0xA1 => IndirectX (2), 0xB1 => IndirectY (2);
-                ldx: 0xA2 => Immediate (2), 0xA6 => ZeroPage (2), 0xB6 => ZeroPageY (2), 0xAE => Absolute (3), 0xBE => AbsoluteY (3);
-                ldy: 0xA0 => Immediate (2), 0xA4 => ZeroPage (2), 0xB4 => ZeroPageX (2), 0xAC => Absolute (3), 0xBC => AbsoluteX (3);
-                lsr: 0x4A => Accumulator (1), 0x46 => ZeroPage (2), 0x56 => ZeroPageX (2), 0x4E => Absolute (3), 0x5E => AbsoluteX (3);
-                nop: 0xEA => Implied (1);
-                ora: 0x09 => Immediate (2), 0x05 => ZeroPage (2), 0x15 => ZeroPageX (2), 0x0D => Absolute (3), 0x1D => AbsoluteX (3), 0x19 => AbsoluteY (3), 0x01 => IndirectX (2), 0x11 => IndirectY (2);
-                pha: 0x48 => Implied (1);
-                php: 0x08 => Implied (1);
-                pla: 0x68 => Implied (1);
-                plp: 0x28 => Implied (1);
-                rol: 0x2A => Accumulator (1), 0x26 => ZeroPage (2), 0x36 => ZeroPageX (2), 0x2E => Absolute (3), 0x3E => AbsoluteX (3);
-                ror: 0x6A => Accumulator (1), 0x66 => ZeroPage (2), 0x76 => ZeroPageX (2), 0x6E => Absolute (3), 0x7E => AbsoluteX (3);
-                rti: 0x40 => Implied (1);
-                rts: 0x60 => Implied (1);
-                sbc: 0xE9 => Immediate (2), 0xE5 => ZeroPage (2), 0xF5 => ZeroPageX (2), 0xED => Absolute (3), 0xFD => AbsoluteX (3), 0xF9 => AbsoluteY (3), 0xE1 => IndirectX (2), 0xF1 => IndirectY (2);
-                sec: 0x38 => Implied (1);
-                sed: 0xF8 => Implied (1);
-                sei: 0x78 => Implied (1);
-                sta: 0x85 => ZeroPage (2), 0x95 => ZeroPageX (2), 0x8D => Absolute (3), 0x9D => AbsoluteX (3), 0x99 => AbsoluteY (3), 0x81 => IndirectX (2), 0x91 => IndirectY (2);
-                stx: 0x86 => ZeroPage (2), 0x96 => ZeroPageY (2), 0x8E => Absolute (3);
-                sty: 0x84 => ZeroPage (2), 0x94 => ZeroPageX (2), 0x8C => Absolute (3);
-                tax: 0xAA => Implied (1);
-                tay: 0xA8 => Implied (1);
-                tsx: 0xBA => Implied (1);
-                txa: 0x8A => Implied (1);
-                txs: 0x9A => Implied (1);
-                tya: 0x98 => Implied (1);
+        match self.variant {
+            CpuVariant::Cmos65C02 => self.disassemble_cmos(operation_address, opcode_and_operands),
+            _ => self.disassemble_nmos(operation_address, opcode_and_operands),
+        }
+    }
+
+    // The 65C02 adds instructions and addressing modes, fixes some timings and turns every
+    // undefined opcode into a NOP. Everything else behaves like the NMOS instruction set.
+    fn handle_cmos_opcode(&mut self, opcode: u8) -> Result<u8, EmulationError> {
+        let cycles = cmos_instructions!(call_op!(opcode) else self.handle_nmos_opcode(opcode))?;
+        if opcode == 0x5C {
+            // The eight cycle NOP reads its operand like the others, then $FFFF until it is done
+            for _ in 0..4 {
+                self.dummy_read(0xFFFF)?;
             }
-        )
+        }
+        Ok(cycles)
+    }
+
+    fn disassemble_cmos(&self, operation_address: u16, opcode_and_operands: [u8; 3]) -> Result<Instruction, EmulationError> {
+        cmos_instructions!(disassemble_op!(operation_address, opcode_and_operands) else self.disassemble_nmos(operation_address, opcode_and_operands))
+    }
+
+    fn handle_nmos_opcode(&mut self, opcode: u8) -> Result<u8, EmulationError> {
+        nmos_instructions!(call_op!(opcode))
+    }
+
+    fn disassemble_nmos(&self, operation_address: u16, opcode_and_operands: [u8; 3]) -> Result<Instruction, EmulationError> {
+        nmos_instructions!(disassemble_op!(operation_address, opcode_and_operands))
     }
 
+    /// Resolves the operand address by peeking at the bus, without making any of the accesses the
+    /// instruction would.
     pub(crate) fn get_operand_address(&self, mode: AddressingMode, register_pc: u16) -> Result<u16, EmulationError> {
         match mode {
             AddressingMode::Immediate => Ok(register_pc),
-            AddressingMode::ZeroPage => Ok(self.bus.read(register_pc)? as u16),
+            AddressingMode::ZeroPage => Ok(self.bus.peek(register_pc)? as u16),
             AddressingMode::ZeroPageX => Ok(self
                 .bus
-                .read(register_pc)?
+                .peek(register_pc)?
                 .wrapping_add(self.register_x) as u16),
             AddressingMode::ZeroPageY => Ok(self
                 .bus
-                .read(register_pc)?
+                .peek(register_pc)?
                 .wrapping_add(self.register_y) as u16),
-            AddressingMode::Absolute => Ok(self.bus.read_word(register_pc)?),
+            AddressingMode::Absolute => Ok(self.bus.peek_word(register_pc)?),
             AddressingMode::AbsoluteX => Ok(self
                 .bus
-                .read_word(register_pc)?
+                .peek_word(register_pc)?
                 .wrapping_add(self.register_x as u16)),
             AddressingMode::AbsoluteY => Ok(self
                 .bus
-                .read_word(register_pc)?
+                .peek_word(register_pc)?
                 .wrapping_add(self.register_y as u16)),
             AddressingMode::Indirect => {
                 // Emulate the 6502 bug of wrapping around the address space when the low byte of the address is 0xFF.
-                let address = self.bus.read_word(register_pc)?;
-                if address & 0x00FF == 0x00FF {
+                // The 65C02 fixes it.
+                let address = self.bus.peek_word(register_pc)?;
+                if address & 0x00FF == 0x00FF && self.variant != CpuVariant::Cmos65C02 {
                     Ok(u16::from_le_bytes([
-                        self.bus.read(address)?,
-                        self.bus.read(address & 0xFF00)?,
+                        self.bus.peek(address)?,
+                        self.bus.peek(address & 0xFF00)?,
                     ]))
                 } else {
-                    Ok(self.bus.read_word(address)?)
+                    Ok(self.bus.peek_word(address)?)
                 }
             }
             AddressingMode::Relative => Ok(register_pc),
             AddressingMode::IndirectX => {
-                let base = self.bus.read(register_pc)?;
+                let base = self.bus.peek(register_pc)?;
                 let ptr = base.wrapping_add(self.register_x);
-                let lo = self.bus.read(ptr as u16)?;
-                let hi = self.bus.read(ptr.wrapping_add(1) as u16)?;
+                let lo = self.bus.peek(ptr as u16)?;
+                let hi = self.bus.peek(ptr.wrapping_add(1) as u16)?;
                 Ok(u16::from_le_bytes([lo, hi]))
             }
             AddressingMode::IndirectY => {
-                let base = self.bus.read(register_pc)?;
-                let lo = self.bus.read(base as u16)?;
-                let hi = self.bus.read(base.wrapping_add(1) as u16)?;
+                let base = self.bus.peek(register_pc)?;
+                let lo = self.bus.peek(base as u16)?;
+                let hi = self.bus.peek(base.wrapping_add(1) as u16)?;
                 let deref_base = u16::from_le_bytes([lo, hi]);
                 Ok(deref_base.wrapping_add(self.register_y as u16))
             }
+            AddressingMode::ZeroPageIndirect => {
+                let base = self.bus.peek(register_pc)?;
+                let lo = self.bus.peek(base as u16)?;
+                let hi = self.bus.peek(base.wrapping_add(1) as u16)?;
+                Ok(u16::from_le_bytes([lo, hi]))
+            }
+            AddressingMode::AbsoluteIndexedIndirect => {
+                let address = self.bus.peek_word(register_pc)?.wrapping_add(self.register_x as u16);
+                Ok(self.bus.peek_word(address)?)
+            }
             _ => Err(EmulationError::UnsuportedAddressingMode),
         }
     }
 
+    /// Resolves the operand address of the executing instruction, making the bus accesses of its
+    /// addressing mode. Also reports whether indexing crossed a page boundary, which costs read
+    /// instructions an extra cycle.
+    fn operand_address(&mut self, mode: AddressingMode, access: Access) -> Result<(u16, bool), EmulationError> {
+        let register_pc = self.register_pc;
+        match mode {
+            AddressingMode::Immediate | AddressingMode::Relative => Ok((register_pc, false)),
+            AddressingMode::ZeroPage => Ok((self.read(register_pc)? as u16, false)),
+            AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+                let base = self.read(register_pc)?;
+                // The unindexed address is read while the index is added
+                self.dummy_read(base as u16)?;
+                let index = if mode == AddressingMode::ZeroPageX { self.register_x } else { self.register_y };
+                Ok((base.wrapping_add(index) as u16, false))
+            }
+            AddressingMode::Absolute => Ok((self.read_word(register_pc)?, false)),
+            AddressingMode::AbsoluteX => {
+                let base = self.read_word(register_pc)?;
+                self.indexed_address(base, self.register_x, access)
+            }
+            AddressingMode::AbsoluteY => {
+                let base = self.read_word(register_pc)?;
+                self.indexed_address(base, self.register_y, access)
+            }
+            AddressingMode::Indirect => {
+                let address = self.read_word(register_pc)?;
+                if self.variant == CpuVariant::Cmos65C02 {
+                    // The 65C02 spends a cycle fixing the page wrap bug, reading the operand again
+                    self.dummy_read(register_pc.wrapping_add(1))?;
+                    Ok((self.read_word(address)?, false))
+                } else if address & 0x00FF == 0x00FF {
+                    let lo = self.read(address)?;
+                    let hi = self.read(address & 0xFF00)?;
+                    Ok((u16::from_le_bytes([lo, hi]), false))
+                } else {
+                    Ok((self.read_word(address)?, false))
+                }
+            }
+            AddressingMode::IndirectX => {
+                let base = self.read(register_pc)?;
+                self.dummy_read(base as u16)?;
+                let ptr = base.wrapping_add(self.register_x);
+                let lo = self.read(ptr as u16)?;
+                let hi = self.read(ptr.wrapping_add(1) as u16)?;
+                Ok((u16::from_le_bytes([lo, hi]), false))
+            }
+            AddressingMode::IndirectY => {
+                let base = self.read(register_pc)?;
+                let lo = self.read(base as u16)?;
+                let hi = self.read(base.wrapping_add(1) as u16)?;
+                self.indexed_address(u16::from_le_bytes([lo, hi]), self.register_y, access)
+            }
+            AddressingMode::ZeroPageIndirect => {
+                let base = self.read(register_pc)?;
+                let lo = self.read(base as u16)?;
+                let hi = self.read(base.wrapping_add(1) as u16)?;
+                Ok((u16::from_le_bytes([lo, hi]), false))
+            }
+            AddressingMode::AbsoluteIndexedIndirect => {
+                let address = self.read_word(register_pc)?.wrapping_add(self.register_x as u16);
+                // The operand is read again while X is added
+                self.dummy_read(register_pc.wrapping_add(1))?;
+                Ok((self.read_word(address)?, false))
+            }
+            _ => Err(EmulationError::UnsuportedAddressingMode),
+        }
+    }
+
+    // The index is added to the low byte first, so the CPU reads from the unfixed address before
+    // carrying into the high byte. Reads skip that cycle when no carry is needed.
+    fn indexed_address(&mut self, base: u16, index: u8, access: Access) -> Result<(u16, bool), EmulationError> {
+        let address = base.wrapping_add(index as u16);
+        let page_crossed = page_crossed(base, address);
+        if page_crossed || access == Access::Write {
+            self.dummy_read((base & 0xFF00) | (address & 0x00FF))?;
+        }
+        Ok((address, page_crossed))
+    }
+
+    // Read-modify-write instructions write the unmodified value back while computing the new
+    // one; the 65C02 reads it again instead. Returns the old and new values.
+    fn read_modify_write(&mut self, address: u16, modify: impl FnOnce(u8) -> u8) -> Result<(u8, u8), EmulationError> {
+        let old = self.read(address)?;
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.dummy_read(address)?;
+        } else {
+            self.dummy_write(address, old)?;
+        }
+        let new = modify(old);
+        self.write(address, new)?;
+        Ok((old, new))
+    }
+
     fn stack_push(&mut self, value: u8) -> Result<(), EmulationError> {
-        self.bus
-            .write(STACK_BASE + self.register_sp as u16, value)?;
+        self.write(STACK_BASE + self.register_sp as u16, value)?;
         self.register_sp = self.register_sp.wrapping_sub(1);
         Ok(())
     }
 
     fn stack_pop(&mut self) -> Result<u8, EmulationError> {
         self.register_sp = self.register_sp.wrapping_add(1);
-        self.bus.read(STACK_BASE + self.register_sp as u16)
+        self.read(STACK_BASE + self.register_sp as u16)
     }
 
     fn stack_push_word(&mut self, value: u16) -> Result<(), EmulationError> {
@@ -220,6 +258,27 @@ impl Cpu {
         Ok(u16::from_le_bytes([lo, hi]))
     }
 
+    // Pulls spend a cycle reading the stack before incrementing the stack pointer.
+    fn stack_pull_delay(&mut self) -> Result<(), EmulationError> {
+        self.dummy_read(STACK_BASE + self.register_sp as u16)
+    }
+
+    /// Pushes the return address and status, then jumps through `vector`. Only BRK pushes the
+    /// status with the break flag set.
+    pub(super) fn interrupt(&mut self, vector: u16, brk: bool) -> Result<(), EmulationError> {
+        self.stack_push_word(self.register_pc)?;
+        let mut state = self.status_flags; // clone
+        state.set_break(brk);
+        state.set_break_2(true);
+        self.stack_push(state.status)?;
+        self.status_flags.set_interrupt(true);
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.status_flags.set_decimal(false);
+        }
+        self.register_pc = self.read_word(vector)?;
+        Ok(())
+    }
+
     fn add_to_register_a(&mut self, data: u8) {
         let sum = self.register_a as u16 + data as u16 + self.status_flags.get_carry() as u16;
 
@@ -235,16 +294,150 @@ impl Cpu {
         self.status_flags.update_zero(self.register_a);
     }
 
+    /// Adds to the accumulator, honoring decimal mode on the variants that have it. Returns the
+    /// extra cycle the 65C02 takes in decimal mode.
+    fn add_with_carry(&mut self, data: u8, mode: AddressingMode) -> Result<u8, EmulationError> {
+        if self.status_flags.get_decimal() && self.variant.has_decimal_mode() {
+            self.add_decimal_to_register_a(data);
+            self.fix_cmos_decimal_flags(mode)
+        } else {
+            self.add_to_register_a(data);
+            Ok(0)
+        }
+    }
+
+    /// Subtracts from the accumulator like `add_with_carry`.
+    fn subtract_with_borrow(&mut self, data: u8, mode: AddressingMode) -> Result<u8, EmulationError> {
+        if self.status_flags.get_decimal() && self.variant.has_decimal_mode() {
+            self.subtract_decimal_from_register_a(data);
+            self.fix_cmos_decimal_flags(mode)
+        } else {
+            self.add_to_register_a((data as i8).wrapping_neg().wrapping_sub(1) as u8);
+            Ok(0)
+        }
+    }
+
+    // The 65C02 spends an extra cycle in decimal mode to set N and Z from the BCD result, reading
+    // the next opcode meanwhile.
+    fn fix_cmos_decimal_flags(&mut self, mode: AddressingMode) -> Result<u8, EmulationError> {
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.dummy_read(self.next_opcode_address(mode))?;
+            self.status_flags.update_negative(self.register_a);
+            self.status_flags.update_zero(self.register_a);
+            Ok(1)
+        } else {
+            Ok(0)
+        }
+    }
+
+    // PC is only moved past the operands once the instruction is done.
+    fn next_opcode_address(&self, mode: AddressingMode) -> u16 {
+        let operand_length = match mode {
+            AddressingMode::Implied | AddressingMode::Accumulator => 0,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect
+            | AddressingMode::AbsoluteIndexedIndirect => 2,
+            _ => 1,
+        };
+        self.register_pc.wrapping_add(operand_length)
+    }
+
+    // On the NMOS 6502, N, V and Z are left in the state of intermediate or binary results when
+    // in decimal mode; only the accumulator and carry hold the BCD result.
+    fn add_decimal_to_register_a(&mut self, data: u8) {
+        let a = self.register_a as u16;
+        let value = data as u16;
+        let carry = self.status_flags.get_carry() as u16;
+
+        let mut low = (a & 0x0F) + (value & 0x0F) + carry;
+        if low > 0x09 {
+            low += 0x06;
+        }
+        let mut result = (low & 0x0F) + (a & 0xF0) + (value & 0xF0);
+        if low > 0x0F {
+            result += 0x10;
+        }
+
+        self.status_flags.update_zero((a + value + carry) as u8);
+        self.status_flags.update_negative(result as u8);
+        self.status_flags.set_overflow((a ^ result) & 0x80 != 0 && (a ^ value) & 0x80 == 0);
+        if result & 0x1F0 > 0x90 {
+            result += 0x60;
+        }
+        self.status_flags.set_carry(result & 0xFF0 > 0xF0);
+        self.register_a = result as u8;
+    }
+
+    fn subtract_decimal_from_register_a(&mut self, data: u8) {
+        let a = self.register_a as u16;
+        let value = data as u16;
+        let borrow = !self.status_flags.get_carry() as u16;
+
+        let binary = a.wrapping_sub(value).wrapping_sub(borrow);
+        let low = (a & 0x0F).wrapping_sub(value & 0x0F).wrapping_sub(borrow);
+        let result = if self.variant == CpuVariant::Cmos65C02 {
+            // The 65C02 adjusts the whole binary result instead of each nibble
+            let mut result = binary;
+            if result & 0x8000 != 0 {
+                result = result.wrapping_sub(0x60);
+            }
+            if low & 0x8000 != 0 {
+                result = result.wrapping_sub(0x06);
+            }
+            result
+        } else {
+            let mut result = if low & 0x10 != 0 {
+                (low.wrapping_sub(0x06) & 0x0F) | (a & 0xF0).wrapping_sub(value & 0xF0).wrapping_sub(0x10)
+            } else {
+                (low & 0x0F) | (a & 0xF0).wrapping_sub(value & 0xF0)
+            };
+            if result & 0x100 != 0 {
+                result = result.wrapping_sub(0x60);
+            }
+            result
+        };
+
+        self.status_flags.set_carry(binary < 0x100);
+        self.status_flags.update_zero(binary as u8);
+        self.status_flags.update_negative(binary as u8);
+        self.status_flags.set_overflow((a ^ binary) & 0x80 != 0 && (a ^ value) & 0x80 != 0);
+        self.register_a = result as u8;
+    }
+
+    // The 65C02 only spends the 7th cycle of indexed shifts and rotates when crossing a page.
+    fn cmos_shift_page_penalty(&self, page_crossed: bool) -> u8 {
+        (page_crossed && self.variant == CpuVariant::Cmos65C02) as u8
+    }
+
+    // ...and only reads from the unfixed address then, like a read instruction.
+    fn shift_access(&self) -> Access {
+        if self.variant == CpuVariant::Cmos65C02 {
+            Access::Read
+        } else {
+            Access::Write
+        }
+    }
+
     fn branch_aux(
         &mut self,
         mode: AddressingMode,
         condition: bool,
     ) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let jump = self.bus.read(addr)? as i8;
+        let (addr, _) = self.operand_address(mode, Access::Read)?;
+        let jump = self.read(addr)? as i8;
         if condition {
-            self.register_pc = addr.wrapping_add(1).wrapping_add(jump as u16);
-            Ok(OpResult::new(0, false))
+            let next = addr.wrapping_add(1);
+            self.register_pc = next.wrapping_add(jump as u16);
+            // A taken branch costs one cycle, plus another if it lands on a different page, each
+            // spent reading from the address computed so far.
+            self.dummy_read(next)?;
+            let page_crossed = page_crossed(next, self.register_pc);
+            if page_crossed {
+                self.dummy_read((next & 0xFF00) | (self.register_pc & 0x00FF))?;
+            }
+            Ok(OpResult::new(1 + page_crossed as u8, false))
         } else {
             Ok(OpResult::new(0, true))
         }
@@ -255,34 +448,34 @@ impl Cpu {
         mode: AddressingMode,
         compare_with: u8,
     ) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let value = self.bus.read(addr)?;
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        let value = self.read(addr)?;
         self.status_flags.set_carry(value <= compare_with);
         let result = compare_with.wrapping_sub(value);
         self.status_flags.update_zero(result);
         self.status_flags.update_negative(result);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(page_crossed as u8, true))
     }
 
-    // Ignoring the decimal mode since it is not used in the NES.
     fn adc(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let value = self.bus.read(addr)?;
-        self.add_to_register_a(value);
-        Ok(OpResult::new(0, true))
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        let value = self.read(addr)?;
+        let decimal_cycles = self.add_with_carry(value, mode)?;
+        Ok(OpResult::new(page_crossed as u8 + decimal_cycles, true))
     }
 
     fn and(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        self.register_a &= self.bus.read(addr)?;
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        self.register_a &= self.read(addr)?;
         self.status_flags.update_negative(self.register_a);
         self.status_flags.update_zero(self.register_a);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(page_crossed as u8, true))
     }
 
     fn asl(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
         let old;
         let new;
+        let mut extra_cycles = 0;
         match mode {
             AddressingMode::Accumulator => {
                 old = self.register_a;
@@ -290,16 +483,15 @@ impl Cpu {
                 new = self.register_a;
             }
             _ => {
-                let addr = self.get_operand_address(mode, self.register_pc)?;
-                old = self.bus.read(addr)?;
-                self.bus.write(addr, old << 1)?;
-                new = old << 1;
+                let (addr, page_crossed) = self.operand_address(mode, self.shift_access())?;
+                extra_cycles = self.cmos_shift_page_penalty(page_crossed);
+                (old, new) = self.read_modify_write(addr, |value| value << 1)?;
             }
         }
         self.status_flags.set_carry(old & 0x80 != 0);
         self.status_flags.update_negative(new);
         self.status_flags.update_zero(new);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(extra_cycles, true))
     }
 
     fn bcc(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
@@ -315,12 +507,15 @@ impl Cpu {
     }
 
     fn bit(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let value = self.bus.read(addr)?;
-        self.status_flags.set_overflow(value & 0x40 != 0);
-        self.status_flags.update_negative(value);
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        let value = self.read(addr)?;
+        // The 65C02's immediate BIT only affects the zero flag
+        if mode != AddressingMode::Immediate {
+            self.status_flags.set_overflow(value & 0x40 != 0);
+            self.status_flags.update_negative(value);
+        }
         self.status_flags.update_zero(self.register_a & value);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(page_crossed as u8, true))
     }
 
     fn bmi(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
@@ -336,8 +531,16 @@ impl Cpu {
     }
 
     fn brk(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        self.halt();
-        Ok(OpResult::new(0, true))
+        // BRK skips the padding byte read after it, and on NMOS CPUs an NMI raised while it runs
+        // hijacks its vector. The 65C02 finishes the BRK and takes the NMI after it.
+        self.register_pc = self.register_pc.wrapping_add(1);
+        let vector = if self.variant != CpuVariant::Cmos65C02 && self.take_late_nmi() {
+            NMI_VECTOR
+        } else {
+            IRQ_VECTOR
+        };
+        self.interrupt(vector, true)?;
+        Ok(OpResult::new(0, false))
     }
 
     fn bvc(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
@@ -359,6 +562,7 @@ impl Cpu {
     }
 
     fn cli(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.delayed_interrupt_flag = Some(self.status_flags.get_interrupt());
         self.status_flags.set_interrupt(false);
         Ok(OpResult::new(0, true))
     }
@@ -381,10 +585,17 @@ impl Cpu {
     }
 
     fn dec(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let mut value = self.bus.read(addr)?;
-        value = value.wrapping_sub(1);
-        self.bus.write(addr, value)?;
+        let value;
+        match mode {
+            AddressingMode::Accumulator => {
+                self.register_a = self.register_a.wrapping_sub(1);
+                value = self.register_a;
+            }
+            _ => {
+                let (addr, _) = self.operand_address(mode, Access::Write)?;
+                (_, value) = self.read_modify_write(addr, |value| value.wrapping_sub(1))?;
+            }
+        }
         self.status_flags.update_negative(value);
         self.status_flags.update_zero(value);
         Ok(OpResult::new(0, true))
@@ -405,19 +616,26 @@ impl Cpu {
     }
 
     fn eor(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let value = self.bus.read(addr)?;
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        let value = self.read(addr)?;
         self.register_a ^= value;
         self.status_flags.update_negative(self.register_a);
         self.status_flags.update_zero(self.register_a);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(page_crossed as u8, true))
     }
 
     fn inc(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let mut value = self.bus.read(addr)?;
-        value = value.wrapping_add(1);
-        self.bus.write(addr, value)?;
+        let value;
+        match mode {
+            AddressingMode::Accumulator => {
+                self.register_a = self.register_a.wrapping_add(1);
+                value = self.register_a;
+            }
+            _ => {
+                let (addr, _) = self.operand_address(mode, Access::Write)?;
+                (_, value) = self.read_modify_write(addr, |value| value.wrapping_add(1))?;
+            }
+        }
         self.status_flags.update_negative(value);
         self.status_flags.update_zero(value);
         Ok(OpResult::new(0, true))
@@ -438,45 +656,49 @@ impl Cpu {
     }
 
     fn jmp(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
+        let (addr, _) = self.operand_address(mode, Access::Read)?;
         self.register_pc = addr;
         Ok(OpResult::new(0, false))
     }
 
-    fn jsr(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
+    fn jsr(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        // The high byte of the target is only read after pushing the return address
+        let lo = self.read(self.register_pc)?;
+        self.dummy_read(STACK_BASE + self.register_sp as u16)?;
         self.stack_push_word(self.register_pc.wrapping_add(1))?;
-        self.register_pc = addr;
+        let hi = self.read(self.register_pc.wrapping_add(1))?;
+        self.register_pc = u16::from_le_bytes([lo, hi]);
         Ok(OpResult::new(0, false))
     }
 
     fn lda(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        self.register_a = self.bus.read(addr)?;
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        self.register_a = self.read(addr)?;
         self.status_flags.update_negative(self.register_a);
         self.status_flags.update_zero(self.register_a);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(page_crossed as u8, true))
     }
 
     fn ldx(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        self.register_x = self.bus.read(addr)?;
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        self.register_x = self.read(addr)?;
         self.status_flags.update_negative(self.register_x);
         self.status_flags.update_zero(self.register_x);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(page_crossed as u8, true))
     }
 
     fn ldy(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        self.register_y = self.bus.read(addr)?;
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        self.register_y = self.read(addr)?;
         self.status_flags.update_negative(self.register_y);
         self.status_flags.update_zero(self.register_y);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(page_crossed as u8, true))
     }
 
     fn lsr(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
         let old;
         let new;
+        let mut extra_cycles = 0;
         match mode {
             AddressingMode::Accumulator => {
                 old = self.register_a;
@@ -484,29 +706,36 @@ impl Cpu {
                 new = self.register_a;
             }
             _ => {
-                let addr = self.get_operand_address(mode, self.register_pc)?;
-                old = self.bus.read(addr)?;
-                self.bus.write(addr, old >> 1)?;
-                new = old >> 1;
+                let (addr, page_crossed) = self.operand_address(mode, self.shift_access())?;
+                extra_cycles = self.cmos_shift_page_penalty(page_crossed);
+                (old, new) = self.read_modify_write(addr, |value| value >> 1)?;
             }
         }
         self.status_flags.set_carry(old & 0x01 != 0);
         self.status_flags.update_negative(new);
         self.status_flags.update_zero(new);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(extra_cycles, true))
     }
 
-    fn nop(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        Ok(OpResult::new(0, true))
+    fn nop(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        match mode {
+            AddressingMode::Implied => Ok(OpResult::new(0, true)),
+            // The unofficial NOPs with operands still read them, paying the page crossing cycle
+            _ => {
+                let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+                self.dummy_read(addr)?;
+                Ok(OpResult::new(page_crossed as u8, true))
+            }
+        }
     }
 
     fn ora(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let value = self.bus.read(addr)?;
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        let value = self.read(addr)?;
         self.register_a |= value;
         self.status_flags.update_negative(self.register_a);
         self.status_flags.update_zero(self.register_a);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(page_crossed as u8, true))
     }
 
     fn pha(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
@@ -523,6 +752,7 @@ impl Cpu {
     }
 
     fn pla(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.stack_pull_delay()?;
         self.register_a = self.stack_pop()?;
         self.status_flags.update_negative(self.register_a);
         self.status_flags.update_zero(self.register_a);
@@ -530,6 +760,8 @@ impl Cpu {
     }
 
     fn plp(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.stack_pull_delay()?;
+        self.delayed_interrupt_flag = Some(self.status_flags.get_interrupt());
         self.status_flags.status = self.stack_pop()?;
         self.status_flags.set_break(false);
         self.status_flags.set_break_2(true);
@@ -539,6 +771,7 @@ impl Cpu {
     fn rol(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
         let old;
         let new;
+        let mut extra_cycles = 0;
         match mode {
             AddressingMode::Accumulator => {
                 old = self.register_a;
@@ -546,21 +779,22 @@ impl Cpu {
                 self.register_a = new;
             }
             _ => {
-                let addr = self.get_operand_address(mode, self.register_pc)?;
-                old = self.bus.read(addr)?;
-                new = old.rotate_left(1) | (self.status_flags.get_carry() as u8);
-                self.bus.write(addr, new)?;
+                let (addr, page_crossed) = self.operand_address(mode, self.shift_access())?;
+                extra_cycles = self.cmos_shift_page_penalty(page_crossed);
+                let carry = self.status_flags.get_carry() as u8;
+                (old, new) = self.read_modify_write(addr, |value| value.rotate_left(1) | carry)?;
             }
         }
         self.status_flags.set_carry(old & 0x80 != 0);
         self.status_flags.update_negative(new);
         self.status_flags.update_zero(new);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(extra_cycles, true))
     }
 
     fn ror(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
         let old;
         let new;
+        let mut extra_cycles = 0;
         match mode {
             AddressingMode::Accumulator => {
                 old = self.register_a;
@@ -568,19 +802,20 @@ impl Cpu {
                 self.register_a = new;
             }
             _ => {
-                let addr = self.get_operand_address(mode, self.register_pc)?;
-                old = self.bus.read(addr)?;
-                new = (old >> 1) | (self.status_flags.get_carry() as u8) << 7;
-                self.bus.write(addr, new)?;
+                let (addr, page_crossed) = self.operand_address(mode, self.shift_access())?;
+                extra_cycles = self.cmos_shift_page_penalty(page_crossed);
+                let carry = self.status_flags.get_carry() as u8;
+                (old, new) = self.read_modify_write(addr, |value| (value >> 1) | carry << 7)?;
             }
         }
         self.status_flags.set_carry(old & 0x01 != 0);
         self.status_flags.update_negative(new);
         self.status_flags.update_zero(new);
-        Ok(OpResult::new(0, true))
+        Ok(OpResult::new(extra_cycles, true))
     }
 
     fn rti(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.stack_pull_delay()?;
         self.status_flags.status = self.stack_pop()?;
         self.status_flags.set_break(false);
         self.status_flags.set_break_2(true);
@@ -589,15 +824,19 @@ impl Cpu {
     }
 
     fn rts(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        self.register_pc = self.stack_pop_word()?.wrapping_add(1);
+        self.stack_pull_delay()?;
+        let address = self.stack_pop_word()?;
+        // The pulled address is read while it is incremented
+        self.dummy_read(address)?;
+        self.register_pc = address.wrapping_add(1);
         Ok(OpResult::new(0, false))
     }
 
     fn sbc(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        let value = self.bus.read(addr)?;
-        self.add_to_register_a((value as i8).wrapping_neg().wrapping_sub(1) as u8);
-        Ok(OpResult::new(0, true))
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        let value = self.read(addr)?;
+        let decimal_cycles = self.subtract_with_borrow(value, mode)?;
+        Ok(OpResult::new(page_crossed as u8 + decimal_cycles, true))
     }
 
     fn sec(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
@@ -611,25 +850,26 @@ impl Cpu {
     }
 
     fn sei(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.delayed_interrupt_flag = Some(self.status_flags.get_interrupt());
         self.status_flags.set_interrupt(true);
         Ok(OpResult::new(0, true))
     }
 
     fn sta(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        self.bus.write(addr, self.register_a)?;
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        self.write(addr, self.register_a)?;
         Ok(OpResult::new(0, true))
     }
 
     fn stx(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        self.bus.write(addr, self.register_x)?;
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        self.write(addr, self.register_x)?;
         Ok(OpResult::new(0, true))
     }
 
     fn sty(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
-        let addr = self.get_operand_address(mode, self.register_pc)?;
-        self.bus.write(addr, self.register_y)?;
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        self.write(addr, self.register_y)?;
         Ok(OpResult::new(0, true))
     }
 
@@ -672,4 +912,186 @@ impl Cpu {
         self.status_flags.update_zero(self.register_a);
         Ok(OpResult::new(0, true))
     }
+
+    // 65C02 opcodes
+
+    fn bra(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.branch_aux(mode, true)
+    }
+
+    fn phx(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.stack_push(self.register_x)?;
+        Ok(OpResult::new(0, true))
+    }
+
+    fn phy(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.stack_push(self.register_y)?;
+        Ok(OpResult::new(0, true))
+    }
+
+    fn plx(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.stack_pull_delay()?;
+        self.register_x = self.stack_pop()?;
+        self.status_flags.update_negative(self.register_x);
+        self.status_flags.update_zero(self.register_x);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn ply(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        self.stack_pull_delay()?;
+        self.register_y = self.stack_pop()?;
+        self.status_flags.update_negative(self.register_y);
+        self.status_flags.update_zero(self.register_y);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn stz(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        self.write(addr, 0)?;
+        Ok(OpResult::new(0, true))
+    }
+
+    fn trb(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        let a = self.register_a;
+        let (value, _) = self.read_modify_write(addr, |value| value & !a)?;
+        self.status_flags.update_zero(self.register_a & value);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn tsb(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        let a = self.register_a;
+        let (value, _) = self.read_modify_write(addr, |value| value | a)?;
+        self.status_flags.update_zero(self.register_a & value);
+        Ok(OpResult::new(0, true))
+    }
+
+    // Unofficial opcodes
+
+    fn alr(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Read)?;
+        let value = self.register_a & self.read(addr)?;
+        self.register_a = value >> 1;
+        self.status_flags.set_carry(value & 0x01 != 0);
+        self.status_flags.update_negative(self.register_a);
+        self.status_flags.update_zero(self.register_a);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn anc(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Read)?;
+        self.register_a &= self.read(addr)?;
+        self.status_flags.set_carry(self.register_a & 0x80 != 0);
+        self.status_flags.update_negative(self.register_a);
+        self.status_flags.update_zero(self.register_a);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn arr(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Read)?;
+        let value = self.register_a & self.read(addr)?;
+        self.register_a = (value >> 1) | (self.status_flags.get_carry() as u8) << 7;
+        self.status_flags.set_carry(self.register_a & 0x40 != 0);
+        self.status_flags.set_overflow(((self.register_a >> 6) ^ (self.register_a >> 5)) & 0x01 != 0);
+        self.status_flags.update_negative(self.register_a);
+        self.status_flags.update_zero(self.register_a);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn axs(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Read)?;
+        let value = self.read(addr)?;
+        let and = self.register_a & self.register_x;
+        self.register_x = and.wrapping_sub(value);
+        self.status_flags.set_carry(value <= and);
+        self.status_flags.update_negative(self.register_x);
+        self.status_flags.update_zero(self.register_x);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn dcp(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        let (_, value) = self.read_modify_write(addr, |value| value.wrapping_sub(1))?;
+        self.status_flags.set_carry(value <= self.register_a);
+        let result = self.register_a.wrapping_sub(value);
+        self.status_flags.update_zero(result);
+        self.status_flags.update_negative(result);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn isb(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        let (_, value) = self.read_modify_write(addr, |value| value.wrapping_add(1))?;
+        self.subtract_with_borrow(value, mode)?;
+        Ok(OpResult::new(0, true))
+    }
+
+    fn jam(&mut self, _mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        // The real CPU locks up, fetching the same opcode forever
+        self.register_pc = self.register_pc.wrapping_sub(1);
+        if self.halt_policy.on_jam {
+            self.halt();
+        }
+        Ok(OpResult::new(0, false))
+    }
+
+    fn lax(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, page_crossed) = self.operand_address(mode, Access::Read)?;
+        self.register_a = self.read(addr)?;
+        self.register_x = self.register_a;
+        self.status_flags.update_negative(self.register_a);
+        self.status_flags.update_zero(self.register_a);
+        Ok(OpResult::new(page_crossed as u8, true))
+    }
+
+    fn rla(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        let carry = self.status_flags.get_carry() as u8;
+        let (old, new) = self.read_modify_write(addr, |value| (value << 1) | carry)?;
+        self.status_flags.set_carry(old & 0x80 != 0);
+        self.register_a &= new;
+        self.status_flags.update_negative(self.register_a);
+        self.status_flags.update_zero(self.register_a);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn rra(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        let carry = self.status_flags.get_carry() as u8;
+        let (old, new) = self.read_modify_write(addr, |value| (value >> 1) | carry << 7)?;
+        self.status_flags.set_carry(old & 0x01 != 0);
+        self.add_with_carry(new, mode)?;
+        Ok(OpResult::new(0, true))
+    }
+
+    fn sax(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        self.write(addr, self.register_a & self.register_x)?;
+        Ok(OpResult::new(0, true))
+    }
+
+    fn slo(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        let (old, new) = self.read_modify_write(addr, |value| value << 1)?;
+        self.status_flags.set_carry(old & 0x80 != 0);
+        self.register_a |= new;
+        self.status_flags.update_negative(self.register_a);
+        self.status_flags.update_zero(self.register_a);
+        Ok(OpResult::new(0, true))
+    }
+
+    fn sre(&mut self, mode: AddressingMode) -> Result<OpResult, EmulationError> {
+        let (addr, _) = self.operand_address(mode, Access::Write)?;
+        let (old, new) = self.read_modify_write(addr, |value| value >> 1)?;
+        self.status_flags.set_carry(old & 0x01 != 0);
+        self.register_a ^= new;
+        self.status_flags.update_negative(self.register_a);
+        self.status_flags.update_zero(self.register_a);
+        Ok(OpResult::new(0, true))
+    }
+}
+
+fn page_crossed(from: u16, to: u16) -> bool {
+    from & 0xFF00 != to & 0xFF00
 }
@@ -0,0 +1,139 @@
+use crate::cpu::{Cpu, CpuStatus, CpuVariant};
+use crate::memory::Bus;
+use crate::EmulationError;
+
+const STATE_MAGIC: &[u8; 4] = b"6502";
+const STATE_VERSION: u8 = 1;
+const STATE_LENGTH: usize = 30;
+
+/// The complete state of a CPU between instructions, without its bus or configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CpuState {
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    pub register_sp: u8,
+    pub register_pc: u16,
+    pub status_flags: CpuStatus,
+    pub variant: CpuVariant,
+    pub halted: bool,
+    pub cycles: u64,
+    pub instructions: u64,
+    pub nmi_line: bool,
+    pub nmi_pending: bool,
+    pub irq_line: bool,
+    /// The I flag interrupts are polled with before the next instruction, when a CLI, SEI or PLP
+    /// has just changed it.
+    pub delayed_interrupt_flag: Option<bool>,
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Captures the CPU state. An instruction in progress from `tick` is not part of it, so the
+    /// registers are the ones from before that instruction, though its bus accesses so far have
+    /// already happened.
+    pub fn snapshot(&self) -> CpuState {
+        let mut state = CpuState {
+            register_a: self.register_a,
+            register_x: self.register_x,
+            register_y: self.register_y,
+            register_sp: self.register_sp,
+            register_pc: self.register_pc,
+            status_flags: self.status_flags,
+            variant: self.variant,
+            halted: self.halted,
+            cycles: self.cycles,
+            instructions: self.instructions,
+            nmi_line: self.nmi_line,
+            nmi_pending: self.nmi_pending,
+            irq_line: self.irq_line,
+            delayed_interrupt_flag: self.delayed_interrupt_flag,
+        };
+        if let Some(partial) = &self.partial_instruction {
+            partial.rewind(&mut state);
+        }
+        state
+    }
+
+    /// Puts the CPU in `state`, dropping any instruction in progress.
+    pub fn restore(&mut self, state: &CpuState) {
+        self.register_a = state.register_a;
+        self.register_x = state.register_x;
+        self.register_y = state.register_y;
+        self.register_sp = state.register_sp;
+        self.register_pc = state.register_pc;
+        self.status_flags = state.status_flags;
+        self.variant = state.variant;
+        self.halted = state.halted;
+        self.cycles = state.cycles;
+        self.instructions = state.instructions;
+        self.nmi_line = state.nmi_line;
+        self.nmi_pending = state.nmi_pending;
+        self.irq_line = state.irq_line;
+        self.delayed_interrupt_flag = state.delayed_interrupt_flag;
+        self.partial_instruction = None;
+    }
+}
+
+impl CpuState {
+    /// Encodes the state in a versioned little endian format that `from_bytes` will keep reading.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(STATE_LENGTH);
+        bytes.extend_from_slice(STATE_MAGIC);
+        bytes.push(STATE_VERSION);
+        bytes.extend_from_slice(&[
+            self.register_a,
+            self.register_x,
+            self.register_y,
+            self.register_sp,
+        ]);
+        bytes.extend_from_slice(&self.register_pc.to_le_bytes());
+        bytes.push(self.status_flags.status);
+        bytes.push(match self.variant {
+            CpuVariant::Nes2A03 => 0,
+            CpuVariant::Nmos6502 => 1,
+            CpuVariant::Cmos65C02 => 2,
+        });
+        bytes.extend_from_slice(&self.cycles.to_le_bytes());
+        bytes.extend_from_slice(&self.instructions.to_le_bytes());
+        let flags = self.halted as u8
+            | (self.nmi_line as u8) << 1
+            | (self.nmi_pending as u8) << 2
+            | (self.irq_line as u8) << 3
+            | (self.delayed_interrupt_flag.is_some() as u8) << 4
+            | ((self.delayed_interrupt_flag == Some(true)) as u8) << 5;
+        bytes.push(flags);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<CpuState, EmulationError> {
+        if bytes.len() != STATE_LENGTH || &bytes[0..4] != STATE_MAGIC {
+            return Err(EmulationError::InvalidState);
+        }
+        if bytes[4] != STATE_VERSION {
+            return Err(EmulationError::UnsupportedStateVersion(bytes[4]));
+        }
+        let variant = match bytes[12] {
+            0 => CpuVariant::Nes2A03,
+            1 => CpuVariant::Nmos6502,
+            2 => CpuVariant::Cmos65C02,
+            _ => return Err(EmulationError::InvalidState),
+        };
+        let flags = bytes[29];
+        Ok(CpuState {
+            register_a: bytes[5],
+            register_x: bytes[6],
+            register_y: bytes[7],
+            register_sp: bytes[8],
+            register_pc: u16::from_le_bytes([bytes[9], bytes[10]]),
+            status_flags: CpuStatus { status: bytes[11] },
+            variant,
+            halted: flags & 0x01 != 0,
+            cycles: u64::from_le_bytes(bytes[13..21].try_into().unwrap()),
+            instructions: u64::from_le_bytes(bytes[21..29].try_into().unwrap()),
+            nmi_line: flags & 0x02 != 0,
+            nmi_pending: flags & 0x04 != 0,
+            irq_line: flags & 0x08 != 0,
+            delayed_interrupt_flag: (flags & 0x10 != 0).then_some(flags & 0x20 != 0),
+        })
+    }
+}
@@ -0,0 +1,606 @@
+use std::fs;
+use std::path::Path;
+use crate::cpu::{Cpu, CpuVariant, HaltPolicy};
+use crate::cpu::cycle::{AccessKind, BusAccess};
+use crate::cpu::opcodes::OPCODES;
+use crate::cpu::state::CpuState;
+use crate::memory::Bus;
+use crate::memory::nes::NesBus;
+use crate::memory::test_game::TestGameBus;
+use crate::rom::Rom;
+use crate::EmulationError;
+
+fn nestest_cpu() -> Cpu<NesBus> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let rom = Rom::new(&fs::read(root.join("roms/nestest.nes")).unwrap()).unwrap();
+    let mut cpu = Cpu::new(NesBus::new(rom));
+    // Automation mode starts at $C000 instead of the reset vector
+    cpu.register_pc = 0xC000;
+    cpu
+}
+
+const PROGRAM_START: u16 = 0x8000;
+const NMI_HANDLER: u16 = 0x9000;
+const IRQ_HANDLER: u16 = 0xA000;
+
+fn program_cpu(program: &[u8]) -> Cpu<TestGameBus> {
+    let mut cpu = Cpu::new(TestGameBus::new());
+    for (i, byte) in program.iter().enumerate() {
+        cpu.bus.write(PROGRAM_START + i as u16, *byte).unwrap();
+    }
+    cpu.bus.write_word(0xFFFA, NMI_HANDLER).unwrap();
+    cpu.bus.write_word(0xFFFE, IRQ_HANDLER).unwrap();
+    cpu.register_pc = PROGRAM_START;
+    cpu
+}
+
+#[test]
+fn test_step_returns_cycles() {
+    let mut cpu = nestest_cpu();
+    let before = cpu.cycles;
+    // JMP $C5F5
+    assert_eq!(cpu.step().unwrap(), 3);
+    assert_eq!(cpu.cycles, before + 3);
+}
+
+#[test]
+fn test_trace_at_end_of_memory() {
+    // LDA $FFFF
+    let cpu = program_cpu(&[0xAD, 0xFF, 0xFF]);
+    let trace = cpu.trace();
+    assert_eq!(trace.data_address, 0xFFFF);
+    assert_eq!(trace.data_at_address, u16::from_le_bytes([cpu.bus.peek(0xFFFF).unwrap(), cpu.bus.peek(0x0000).unwrap()]));
+}
+
+#[test]
+fn test_unofficial_opcodes_can_be_rejected() {
+    let mut cpu = nestest_cpu();
+    cpu.allow_unofficial_opcodes = false;
+    // *NOP $A9
+    cpu.register_pc = 0xC6BD;
+    assert!(matches!(cpu.step(), Err(EmulationError::InvalidOpcode(0x04))));
+    assert!(cpu.halted);
+}
+
+#[test]
+fn test_unofficial_opcodes_are_marked() {
+    let cpu = nestest_cpu();
+    let instruction = cpu.disassemble(0xC6BD, [0x04, 0xA9, 0x00]).unwrap();
+    assert!(instruction.unofficial);
+    assert_eq!(instruction.to_string(), "*NOP $A9");
+}
+
+#[test]
+fn test_nmi_is_edge_triggered() {
+    // NOP; NOP; NOP
+    let mut cpu = program_cpu(&[0xEA, 0xEA, 0xEA]);
+    cpu.status_flags.set_interrupt(true);
+    cpu.set_nmi_line(true);
+    assert_eq!(cpu.step().unwrap(), 7);
+    assert_eq!(cpu.register_pc, NMI_HANDLER);
+    assert_eq!(cpu.register_sp, 0xFA);
+    // Hardware interrupts push the status with the break flag clear
+    assert_eq!(cpu.bus.peek(0x01FB).unwrap(), 0x24 | 0b0000_0100);
+    assert_eq!(cpu.bus.peek_word(0x01FC).unwrap(), PROGRAM_START);
+
+    // Holding the line does not raise another NMI
+    cpu.register_pc = PROGRAM_START;
+    cpu.set_nmi_line(true);
+    assert_eq!(cpu.step().unwrap(), 2);
+    cpu.set_nmi_line(false);
+    cpu.set_nmi_line(true);
+    cpu.step().unwrap();
+    assert_eq!(cpu.register_pc, NMI_HANDLER);
+}
+
+#[test]
+fn test_irq_is_level_triggered_and_masked() {
+    // CLI; NOP
+    let mut cpu = program_cpu(&[0x58, 0xEA]);
+    cpu.bus.write(IRQ_HANDLER, 0x40).unwrap(); // RTI
+    cpu.status_flags.set_interrupt(true);
+    cpu.set_irq_line(true);
+    cpu.step().unwrap();
+    assert_eq!(cpu.register_pc, PROGRAM_START + 1);
+    // The poll after CLI still sees the interrupt disable flag set
+    cpu.step().unwrap();
+    assert_eq!(cpu.register_pc, PROGRAM_START + 2);
+    cpu.step().unwrap();
+    assert_eq!(cpu.register_pc, IRQ_HANDLER);
+    assert!(cpu.status_flags.get_interrupt());
+    // RTI restores the I flag, and the line is still active
+    cpu.step().unwrap();
+    assert_eq!(cpu.register_pc, PROGRAM_START + 2);
+    cpu.step().unwrap();
+    assert_eq!(cpu.register_pc, IRQ_HANDLER);
+}
+
+#[test]
+fn test_brk_pushes_break_flag() {
+    // BRK; padding
+    let mut cpu = program_cpu(&[0x00, 0xFF]);
+    assert_eq!(cpu.step().unwrap(), 7);
+    assert!(!cpu.halted);
+    assert_eq!(cpu.register_pc, IRQ_HANDLER);
+    assert_eq!(cpu.bus.peek(0x01FB).unwrap(), 0x24 | 0b0001_0000);
+    assert_eq!(cpu.bus.peek_word(0x01FC).unwrap(), PROGRAM_START + 2);
+}
+
+// Ticks the first cycles of BRK, raises an NMI and finishes it.
+fn brk_interrupted_by_nmi(variant: CpuVariant) -> Cpu<TestGameBus> {
+    // BRK; padding
+    let mut cpu = program_cpu(&[0x00, 0xFF]);
+    cpu.variant = variant;
+    cpu.tick().unwrap();
+    cpu.tick().unwrap();
+    cpu.set_nmi_line(true);
+    cpu.finish_instruction().unwrap();
+    cpu
+}
+
+#[test]
+fn test_nmi_hijacks_brk() {
+    let mut cpu = brk_interrupted_by_nmi(CpuVariant::Nmos6502);
+    assert_eq!(cpu.register_pc, NMI_HANDLER);
+    // The status is still pushed by BRK, with the break flag set
+    assert_eq!(cpu.bus.peek(0x01FB).unwrap(), 0x24 | 0b0001_0000);
+    assert_eq!(cpu.bus.peek_word(0x01FC).unwrap(), PROGRAM_START + 2);
+    // The NMI was taken by the BRK
+    cpu.bus.write(NMI_HANDLER, 0xEA).unwrap();
+    cpu.step().unwrap();
+    assert_eq!(cpu.register_pc, NMI_HANDLER + 1);
+}
+
+#[test]
+fn test_cmos_nmi_does_not_hijack_brk() {
+    let mut cpu = brk_interrupted_by_nmi(CpuVariant::Cmos65C02);
+    assert_eq!(cpu.register_pc, IRQ_HANDLER);
+    assert_eq!(cpu.bus.peek(0x01FB).unwrap(), 0x24 | 0b0001_0000);
+    // The NMI is taken after the BRK instead
+    assert_eq!(cpu.step().unwrap(), 7);
+    assert_eq!(cpu.register_pc, NMI_HANDLER);
+    assert_eq!(cpu.bus.peek_word(0x01F9).unwrap(), IRQ_HANDLER);
+}
+
+#[test]
+fn test_default_halt_policy_never_halts() {
+    // JMP $8000
+    let mut cpu = program_cpu(&[0x4C, 0x00, 0x80]);
+    for _ in 0..100 {
+        cpu.step().unwrap();
+    }
+    assert!(!cpu.halted);
+    assert_eq!(cpu.instructions, 100);
+}
+
+#[test]
+fn test_halt_on_brk_and_jam() {
+    // NOP; BRK
+    let mut cpu = program_cpu(&[0xEA, 0x00]);
+    cpu.halt_policy.on_brk = true;
+    cpu.step().unwrap();
+    let (cycles, instructions) = (cpu.cycles, cpu.instructions);
+    // BRK never runs, so it takes no cycles and isn't counted
+    assert_eq!(cpu.step().unwrap(), 0);
+    assert!(cpu.halted);
+    assert_eq!(cpu.register_pc, PROGRAM_START + 1);
+    assert_eq!((cpu.cycles, cpu.instructions), (cycles, instructions));
+    assert!(matches!(cpu.step(), Err(EmulationError::Halted)));
+
+    let mut cpu = program_cpu(&[0x00]);
+    cpu.halt_policy.on_brk = true;
+    assert!(matches!(cpu.tick(), Err(EmulationError::Halted)));
+    assert!(cpu.halted && !cpu.mid_instruction());
+    assert_eq!(cpu.register_pc, PROGRAM_START);
+    assert_eq!((cpu.cycles, cpu.instructions), (7, 0));
+
+    // NOP; JAM
+    let mut cpu = program_cpu(&[0xEA, 0x02]);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert!(!cpu.halted);
+    assert_eq!(cpu.register_pc, PROGRAM_START + 1);
+    cpu.halt_policy.on_jam = true;
+    cpu.step().unwrap();
+    assert!(cpu.halted);
+    assert_eq!(cpu.register_pc, PROGRAM_START + 1);
+}
+
+#[test]
+fn test_halt_on_self_jump() {
+    // LDA #$01; BNE *
+    let mut cpu = program_cpu(&[0xA9, 0x01, 0xD0, 0xFE]);
+    cpu.halt_policy.on_self_jump = true;
+    cpu.step().unwrap();
+    assert!(!cpu.halted);
+    cpu.step().unwrap();
+    assert!(cpu.halted);
+    assert_eq!(cpu.register_pc, PROGRAM_START + 2);
+}
+
+#[test]
+fn test_halt_after_limits() {
+    // NOP; JMP $8000
+    let program = [0xEA, 0x4C, 0x00, 0x80];
+    let mut cpu = program_cpu(&program);
+    cpu.halt_policy = HaltPolicy {
+        max_instructions: Some(3),
+        ..HaltPolicy::default()
+    };
+    while !cpu.halted {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.instructions, 3);
+
+    let mut cpu = program_cpu(&program);
+    cpu.halt_policy = HaltPolicy {
+        max_cycles: Some(20),
+        ..HaltPolicy::default()
+    };
+    while !cpu.halted {
+        cpu.step().unwrap();
+    }
+    // 7 reset cycles, then 2 + 3 for each loop
+    assert_eq!(cpu.cycles, 22);
+}
+
+fn decimal_result(variant: CpuVariant, program: &[u8]) -> (u8, bool) {
+    let mut cpu = program_cpu(program);
+    cpu.variant = variant;
+    cpu.status_flags.set_decimal(true);
+    while cpu.register_pc < PROGRAM_START + program.len() as u16 {
+        cpu.step().unwrap();
+    }
+    (cpu.register_a, cpu.status_flags.get_carry())
+}
+
+#[test]
+fn test_decimal_mode() {
+    // CLC; LDA #$15; ADC #$27
+    assert_eq!(decimal_result(CpuVariant::Nmos6502, &[0x18, 0xA9, 0x15, 0x69, 0x27]), (0x42, false));
+    // CLC; LDA #$99; ADC #$01
+    assert_eq!(decimal_result(CpuVariant::Nmos6502, &[0x18, 0xA9, 0x99, 0x69, 0x01]), (0x00, true));
+    // SEC; LDA #$42; SBC #$15
+    assert_eq!(decimal_result(CpuVariant::Nmos6502, &[0x38, 0xA9, 0x42, 0xE9, 0x15]), (0x27, true));
+    // SEC; LDA #$00; SBC #$01
+    assert_eq!(decimal_result(CpuVariant::Nmos6502, &[0x38, 0xA9, 0x00, 0xE9, 0x01]), (0x99, false));
+    // The 2A03 ignores the decimal flag
+    assert_eq!(decimal_result(CpuVariant::Nes2A03, &[0x18, 0xA9, 0x15, 0x69, 0x27]), (0x3C, false));
+}
+
+fn cmos_cpu(program: &[u8]) -> Cpu<TestGameBus> {
+    let mut cpu = program_cpu(program);
+    cpu.variant = CpuVariant::Cmos65C02;
+    cpu
+}
+
+#[test]
+fn test_cmos_opcodes_are_all_defined() {
+    let cpu = cmos_cpu(&[]);
+    for opcode in 0..=0xFF {
+        let instruction = cpu.disassemble(0x8000, [opcode, 0x00, 0x00]).unwrap();
+        assert!(!instruction.unofficial, "{:02X}", opcode);
+    }
+    assert_eq!(cpu.disassemble(0x8000, [0x80, 0x10, 0x00]).unwrap().to_string(), "BRA $8012");
+    assert_eq!(cpu.disassemble(0x8000, [0xB2, 0x10, 0x00]).unwrap().to_string(), "LDA ($10)");
+    assert_eq!(cpu.disassemble(0x8000, [0x7C, 0x34, 0x12]).unwrap().to_string(), "JMP ($1234,X)");
+}
+
+#[test]
+fn test_cmos_instructions() {
+    // LDA #$0F; STA $10; LDX #$F0; PHX; PLY; TSB $10; TRB $10; STZ $11; BRA +1; BRK; LDA ($10)
+    let mut cpu = cmos_cpu(&[
+        0xA9, 0x0F, 0x85, 0x10, 0xA2, 0xF0, 0xDA, 0x7A, 0x04, 0x10, 0x14, 0x10, 0x64, 0x11,
+        0x80, 0x01, 0x00, 0xB2, 0x10,
+    ]);
+    cpu.bus.write(0x0000, 0x42).unwrap();
+    for _ in 0..5 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.register_y, 0xF0);
+    assert!(cpu.status_flags.get_negative());
+    cpu.step().unwrap();
+    // TSB leaves Z clear since A & M != 0
+    assert_eq!(cpu.bus.peek(0x10).unwrap(), 0x0F);
+    assert!(!cpu.status_flags.get_zero());
+    cpu.step().unwrap();
+    assert_eq!(cpu.bus.peek(0x10).unwrap(), 0x00);
+    cpu.bus.write(0x11, 0xFF).unwrap();
+    cpu.step().unwrap();
+    assert_eq!(cpu.bus.peek(0x11).unwrap(), 0x00);
+    assert_eq!(cpu.step().unwrap(), 3);
+    assert_eq!(cpu.register_pc, PROGRAM_START + 17);
+    // LDA ($10) reads through the pointer at $10, which now points at $0000
+    assert_eq!(cpu.step().unwrap(), 5);
+    assert_eq!(cpu.register_a, 0x42);
+}
+
+#[test]
+fn test_cmos_fixes_indirect_jump() {
+    // JMP ($10FF)
+    let program = [0x6C, 0xFF, 0x10];
+    let mut cpu = cmos_cpu(&program);
+    cpu.bus.write(0x10FF, 0x34).unwrap();
+    cpu.bus.write(0x1100, 0x12).unwrap();
+    cpu.bus.write(0x1000, 0x56).unwrap();
+    assert_eq!(cpu.step().unwrap(), 6);
+    assert_eq!(cpu.register_pc, 0x1234);
+
+    let mut cpu = program_cpu(&program);
+    cpu.bus.write(0x10FF, 0x34).unwrap();
+    cpu.bus.write(0x1000, 0x56).unwrap();
+    assert_eq!(cpu.step().unwrap(), 5);
+    assert_eq!(cpu.register_pc, 0x5634);
+}
+
+#[test]
+fn test_cmos_decimal_flags() {
+    // SED; CLC; LDA #$99; ADC #$01
+    let mut cpu = cmos_cpu(&[0xF8, 0x18, 0xA9, 0x99, 0x69, 0x01]);
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.step().unwrap(), 3);
+    assert_eq!(cpu.register_a, 0x00);
+    assert!(cpu.status_flags.get_carry());
+    // The NMOS 6502 sets Z from the binary result instead
+    assert!(cpu.status_flags.get_zero());
+    assert!(!cpu.status_flags.get_negative());
+
+    // SEC; LDA #$00; SBC #$01
+    assert_eq!(decimal_result(CpuVariant::Cmos65C02, &[0x38, 0xA9, 0x00, 0xE9, 0x01]), (0x99, false));
+}
+
+#[test]
+fn test_cmos_shift_timing() {
+    // LDX #$01; ASL $1000,X; ASL $10FF,X
+    let mut cpu = cmos_cpu(&[0xA2, 0x01, 0x1E, 0x00, 0x10, 0x1E, 0xFF, 0x10]);
+    cpu.step().unwrap();
+    assert_eq!(cpu.step().unwrap(), 6);
+    assert_eq!(cpu.step().unwrap(), 7);
+}
+
+fn tick_instruction<B: Bus>(cpu: &mut Cpu<B>) -> Vec<BusAccess> {
+    let mut accesses = vec![cpu.tick().unwrap()];
+    while cpu.mid_instruction() {
+        accesses.push(cpu.tick().unwrap());
+    }
+    accesses
+}
+
+// The unstable NMOS opcodes that aren't emulated
+const UNEMULATED_OPCODES: [u8; 8] = [0x8B, 0x93, 0x9B, 0x9C, 0x9E, 0x9F, 0xAB, 0xBB];
+
+#[test]
+fn test_tick_matches_step() {
+    for variant in [CpuVariant::Nmos6502, CpuVariant::Cmos65C02] {
+        for opcode in 0..=0xFF {
+            for (index, status) in [(0x00, 0x24), (0xFF, 0xEF)] {
+                // Operands and pointers that cross pages when indexed by $FF
+                let setup = || {
+                    let mut cpu = program_cpu(&[opcode, 0xF0, 0x02]);
+                    cpu.variant = variant;
+                    cpu.register_x = index;
+                    cpu.register_y = index;
+                    cpu.status_flags.status = status;
+                    cpu.bus.write_word(0x00EF, 0x02F0).unwrap();
+                    cpu.bus.write_word(0x00F0, 0x02F0).unwrap();
+                    cpu
+                };
+                let mut stepped = setup();
+                let mut ticked = setup();
+                let context = format!("{:?} {:02X} index {:02X}", variant, opcode, index);
+                if variant != CpuVariant::Cmos65C02 && UNEMULATED_OPCODES.contains(&opcode) {
+                    assert!(stepped.step().is_err(), "{}", context);
+                    assert!(ticked.tick().is_err(), "{}", context);
+                    continue;
+                }
+                let cycles = stepped.step().unwrap_or_else(|e| panic!("{}: {}", context, e));
+                let previewed = setup().preview();
+                let accesses = tick_instruction(&mut ticked);
+                assert_eq!(previewed, accesses, "{}", context);
+                assert_eq!(accesses.len(), cycles as usize, "{}", context);
+                assert_eq!(accesses[0], BusAccess { address: PROGRAM_START, value: opcode, kind: AccessKind::OpcodeFetch });
+                assert_eq!(ticked.cycles, stepped.cycles, "{}", context);
+                assert_eq!(ticked.trace().to_string(), stepped.trace().to_string(), "{}", context);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_tick_makes_dummy_accesses() {
+    use AccessKind::*;
+    let access = |address, value, kind| BusAccess { address, value, kind };
+
+    // LDA $02F0,X reads the wrong page before fixing the address
+    let mut cpu = program_cpu(&[0xBD, 0xF0, 0x02]);
+    cpu.register_x = 0x20;
+    cpu.bus.write(0x0310, 0x42).unwrap();
+    assert_eq!(tick_instruction(&mut cpu), [
+        access(0x8000, 0xBD, OpcodeFetch),
+        access(0x8001, 0xF0, Read),
+        access(0x8002, 0x02, Read),
+        access(0x0210, 0x00, DummyRead),
+        access(0x0310, 0x42, Read),
+    ]);
+    assert_eq!(cpu.register_a, 0x42);
+
+    // INC $10 writes the old value back before the new one
+    let mut cpu = program_cpu(&[0xE6, 0x10]);
+    cpu.bus.write(0x10, 0x41).unwrap();
+    assert_eq!(tick_instruction(&mut cpu), [
+        access(0x8000, 0xE6, OpcodeFetch),
+        access(0x8001, 0x10, Read),
+        access(0x0010, 0x41, Read),
+        access(0x0010, 0x41, DummyWrite),
+        access(0x0010, 0x42, Write),
+    ]);
+
+    // TAX reads the byte after the opcode
+    let mut cpu = program_cpu(&[0xAA, 0x99]);
+    assert_eq!(tick_instruction(&mut cpu)[1], access(0x8001, 0x99, DummyRead));
+
+    // The 65C02's JMP ($0210) reads its operand again instead of the jump target
+    let mut cpu = cmos_cpu(&[0x6C, 0x10, 0x02]);
+    cpu.bus.write_word(0x0210, 0x1234).unwrap();
+    assert_eq!(tick_instruction(&mut cpu), [
+        access(0x8000, 0x6C, OpcodeFetch),
+        access(0x8001, 0x10, Read),
+        access(0x8002, 0x02, Read),
+        access(0x8002, 0x02, DummyRead),
+        access(0x0210, 0x34, Read),
+        access(0x0211, 0x12, Read),
+    ]);
+    assert_eq!(cpu.register_pc, 0x1234);
+}
+
+#[test]
+fn test_tick_accesses_bus_on_its_cycle() {
+    // LDA #$42; STA $0200
+    let mut cpu = program_cpu(&[0xA9, 0x42, 0x8D, 0x00, 0x02]);
+    cpu.step().unwrap();
+    for _ in 0..3 {
+        cpu.tick().unwrap();
+        assert!(cpu.mid_instruction());
+        assert_eq!(cpu.bus.peek(0x0200).unwrap(), 0x00);
+    }
+    // Registers only change once the instruction is done
+    assert_eq!(cpu.register_pc, PROGRAM_START + 2);
+    cpu.tick().unwrap();
+    assert!(!cpu.mid_instruction());
+    assert_eq!(cpu.bus.peek(0x0200).unwrap(), 0x42);
+    assert_eq!(cpu.register_pc, PROGRAM_START + 5);
+
+    // JAM only halts on its last cycle, and not on the earlier runs of the instruction
+    let mut cpu = program_cpu(&[0x02]);
+    cpu.halt_policy.on_jam = true;
+    cpu.tick().unwrap();
+    assert!(!cpu.halted);
+    assert!(cpu.mid_instruction());
+    cpu.tick().unwrap();
+    assert!(cpu.halted);
+    assert_eq!(cpu.register_pc, PROGRAM_START);
+
+    // An NMI raised mid-BRK stays pending until the run that finishes the BRK takes it
+    let mut cpu = program_cpu(&[0x00, 0xFF]);
+    cpu.tick().unwrap();
+    cpu.set_nmi_line(true);
+    for _ in 0..5 {
+        cpu.tick().unwrap();
+        assert!(cpu.snapshot().nmi_pending);
+    }
+    cpu.tick().unwrap();
+    assert!(!cpu.snapshot().nmi_pending);
+    assert_eq!(cpu.register_pc, NMI_HANDLER);
+
+    // Stepping finishes an instruction started by ticking
+    let mut cpu = program_cpu(&[0xA9, 0x42, 0x8D, 0x00, 0x02]);
+    cpu.step().unwrap();
+    cpu.tick().unwrap();
+    assert_eq!(cpu.step().unwrap(), 4);
+    assert_eq!(cpu.bus.peek(0x0200).unwrap(), 0x42);
+}
+
+#[test]
+fn test_opcode_table_matches_instruction_set() {
+    for variant in [CpuVariant::Nmos6502, CpuVariant::Cmos65C02] {
+        let mut cpu = program_cpu(&[]);
+        cpu.variant = variant;
+        for opcode in 0..=0xFF {
+            let info = variant.opcodes()[opcode as usize];
+            let Ok(instruction) = cpu.disassemble(0x8000, [opcode, 0x00, 0x00]) else {
+                assert_eq!(info, None, "{:02X}", opcode);
+                continue;
+            };
+            let info = info.unwrap();
+            assert_eq!(info.mnemonic, instruction.instruction, "{:02X}", opcode);
+            assert_eq!(info.addressing_mode, instruction.addressing_mode, "{:02X}", opcode);
+            assert_eq!(info.length as u16, instruction.length, "{:02X}", opcode);
+            assert_eq!(info.unofficial, instruction.unofficial, "{:02X}", opcode);
+        }
+    }
+    let official = OPCODES.iter().flatten().filter(|info| !info.unofficial).count();
+    assert_eq!(official, 151);
+
+    // LDA #$00 takes its base cycles
+    let mut cpu = program_cpu(&[0xA9, 0x00]);
+    assert_eq!(cpu.step().unwrap(), OPCODES[0xA9].unwrap().cycles);
+}
+
+#[test]
+fn test_snapshot_and_restore() {
+    // LDX #$00; INX; TXA; ADC #$03; PHA; PLA; BNE -8
+    let program = [0xA2, 0x00, 0xE8, 0x8A, 0x69, 0x03, 0x48, 0x68, 0xD0, 0xF8];
+    let mut cpu = program_cpu(&program);
+    for _ in 0..50 {
+        cpu.step().unwrap();
+    }
+    let state = cpu.snapshot();
+    let expected: Vec<String> = (0..100).map(|_| {
+        let trace = cpu.trace().to_string();
+        cpu.step().unwrap();
+        trace
+    }).collect();
+
+    let mut restored = program_cpu(&program);
+    restored.restore(&state);
+    assert_eq!(restored.snapshot(), state);
+    for expected in &expected {
+        assert_eq!(&restored.trace().to_string(), expected);
+        restored.step().unwrap();
+    }
+
+    // Snapshots mid-instruction are taken from before it
+    let mut cpu = program_cpu(&[0xA9, 0x42]);
+    let before = cpu.snapshot();
+    cpu.tick().unwrap();
+    assert_eq!(cpu.snapshot(), before);
+    cpu.restore(&before);
+    assert!(!cpu.mid_instruction());
+}
+
+#[test]
+fn test_state_encoding() {
+    let mut cpu = program_cpu(&[0x78]);
+    cpu.variant = CpuVariant::Cmos65C02;
+    cpu.register_a = 0x12;
+    cpu.status_flags.set_interrupt(false);
+    cpu.cycles = 0x0123_4567_89AB;
+    // SEI delays the I flag change
+    cpu.step().unwrap();
+    cpu.set_irq_line(true);
+    let state = cpu.snapshot();
+    assert_eq!(state.delayed_interrupt_flag, Some(false));
+
+    let bytes = state.to_bytes();
+    assert_eq!(&bytes[0..5], b"6502\x01");
+    assert_eq!(CpuState::from_bytes(&bytes).unwrap(), state);
+
+    let mut other_version = bytes.clone();
+    other_version[4] = 2;
+    assert!(matches!(CpuState::from_bytes(&other_version), Err(EmulationError::UnsupportedStateVersion(2))));
+    assert!(matches!(CpuState::from_bytes(&bytes[..20]), Err(EmulationError::InvalidState)));
+}
+
+#[test]
+fn test_run_until_trap() {
+    // LDX #$03; loop: DEX; BNE loop; trap: JMP trap
+    let mut cpu = program_cpu(&[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x4C, 0x05, 0x80]);
+    let report = cpu.run_until_trap(100, 3);
+    assert!(report.trapped);
+    assert!(report.error.is_none());
+    assert_eq!(report.address, 0x8005);
+    assert_eq!(report.instructions, 8);
+    assert_eq!(report.trace.iter().map(|trace| trace.address).collect::<Vec<_>>(), vec![0x8002, 0x8003, 0x8005]);
+    assert!(report.to_string().starts_with("Trapped at $8005\nafter 8 instructions"));
+    // The CPU's own halt policy is left alone
+    assert_eq!(cpu.halt_policy, HaltPolicy::default());
+
+    // LDX #$00; loop: DEX; BNE loop
+    let mut cpu = program_cpu(&[0xA2, 0x00, 0xCA, 0xD0, 0xFD]);
+    let report = cpu.run_until_trap(10, 0);
+    assert!(!report.trapped);
+    assert_eq!(report.instructions, 10);
+    assert!(report.trace.is_empty());
+}
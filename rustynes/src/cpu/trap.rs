@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use crate::cpu::disassembly::Trace;
+use crate::cpu::{Cpu, HaltPolicy};
+use crate::memory::Bus;
+use crate::EmulationError;
+
+/// Where a test program stopped, and the instructions that led there.
+pub struct TrapReport {
+    /// PC once the program stopped, which is the trap loop when it trapped.
+    pub address: u16,
+    pub trapped: bool,
+    /// The error that stopped the CPU instead, if any.
+    pub error: Option<EmulationError>,
+    pub instructions: u64,
+    pub cycles: u64,
+    /// The last instructions run, oldest first, ending with the trap.
+    pub trace: Vec<Trace>,
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Runs until the program traps itself in an instruction that jumps or branches to itself,
+    /// which is how test programs such as Klaus Dormann's report success or failure. Gives up
+    /// after `max_instructions`, and keeps a trace of the last `trace_length` instructions.
+    pub fn run_until_trap(&mut self, max_instructions: u64, trace_length: usize) -> TrapReport {
+        let halt_policy = self.halt_policy;
+        self.halt_policy = HaltPolicy {
+            on_self_jump: true,
+            max_instructions: Some(self.instructions + max_instructions),
+            ..HaltPolicy::default()
+        };
+        let (start_instructions, start_cycles) = (self.instructions, self.cycles);
+
+        let mut trace = VecDeque::with_capacity(trace_length);
+        let mut error = None;
+        let mut last_address = None;
+        while !self.halted {
+            last_address = Some(self.register_pc);
+            if trace_length > 0 {
+                if trace.len() == trace_length {
+                    trace.pop_front();
+                }
+                trace.push_back(self.trace());
+            }
+            if let Err(e) = self.step() {
+                error = Some(e);
+            }
+        }
+        let trapped = error.is_none() && last_address == Some(self.register_pc);
+        self.halt_policy = halt_policy;
+
+        TrapReport {
+            address: self.register_pc,
+            trapped,
+            error,
+            instructions: self.instructions - start_instructions,
+            cycles: self.cycles - start_cycles,
+            trace: trace.into(),
+        }
+    }
+}
+
+impl Display for TrapReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.error, self.trapped) {
+            (Some(e), _) => writeln!(f, "Stopped at ${:04X} by an error: {}", self.address, e)?,
+            (None, true) => writeln!(f, "Trapped at ${:04X}", self.address)?,
+            (None, false) => writeln!(f, "Gave up at ${:04X} without trapping", self.address)?,
+        }
+        writeln!(f, "after {} instructions and {} cycles, last ran:", self.instructions, self.cycles)?;
+        for trace in &self.trace {
+            writeln!(f, "{}", trace)?;
+        }
+        Ok(())
+    }
+}
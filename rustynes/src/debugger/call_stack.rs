@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use crate::cpu::Cpu;
+use crate::cpu::cycle::BusAccess;
+use crate::debugger::executed_opcode;
+use crate::memory::Bus;
+use crate::EmulationError;
+
+const BRK: u8 = 0x00;
+const JSR: u8 = 0x20;
+const RTI: u8 = 0x40;
+const RTS: u8 = 0x60;
+const MAX_WARNINGS: usize = 256;
+
+/// A subroutine call or interrupt that hasn't returned yet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CallFrame {
+    /// The address of the JSR or BRK, or of the instruction an NMI or IRQ interrupted.
+    pub caller: u16,
+    pub target: u16,
+    /// Where the matching RTS or RTI is expected to return to.
+    pub return_address: u16,
+    /// The stack pointer from before the call, which returning restores.
+    pub stack_pointer: u8,
+    pub interrupt: bool,
+}
+
+/// Stack manipulation that didn't match the calls made so far.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StackWarning {
+    /// An RTS or RTI at `address` returned to `target` without a call to return from, or through
+    /// an address pushed by hand, such as with the PHA/PHA/RTS jump table trick.
+    UnbalancedReturn { address: u16, target: u16 },
+    /// The instruction at `address` moved the stack pointer past frames without returning from
+    /// them, such as by pulling a return address or with a TXS.
+    DiscardedFrames { address: u16, count: usize },
+}
+
+/// A shadow of the calls on the stack, kept by watching each instruction as it is stepped.
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+    warnings: VecDeque<StackWarning>,
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack::default()
+    }
+
+    /// Steps the CPU and follows the call or return it made.
+    pub fn step<B: Bus>(&mut self, cpu: &mut Cpu<B>) -> Result<u8, EmulationError> {
+        if cpu.mid_instruction() {
+            return cpu.step();
+        }
+        let accesses = cpu.preview();
+        let (address, stack_pointer) = (cpu.register_pc, cpu.register_sp);
+        let result = cpu.step();
+        self.update(cpu, address, stack_pointer, &accesses);
+        result
+    }
+
+    /// Follows a step that started at `address` with `stack_pointer` and made `accesses`.
+    pub(super) fn update<B: Bus>(&mut self, cpu: &Cpu<B>, address: u16, stack_pointer: u8, accesses: &[BusAccess]) {
+        if cpu.halted {
+            return;
+        }
+        let opcode = executed_opcode(accesses);
+        let frame = |return_address, interrupt| CallFrame {
+            caller: address,
+            target: cpu.register_pc,
+            return_address,
+            stack_pointer,
+            interrupt,
+        };
+        match opcode {
+            // Serviced an NMI or IRQ instead of running an instruction
+            None => self.frames.push(frame(address, true)),
+            Some(JSR) => self.frames.push(frame(address.wrapping_add(3), false)),
+            Some(BRK) => self.frames.push(frame(address.wrapping_add(2), true)),
+            Some(RTS | RTI) => {
+                let returned = self.frames.iter().rposition(|frame| {
+                    frame.return_address == cpu.register_pc && frame.stack_pointer == cpu.register_sp
+                });
+                match returned {
+                    Some(index) => {
+                        self.discard_above(index + 1, address);
+                        self.frames.pop();
+                    }
+                    None => self.warn(StackWarning::UnbalancedReturn { address, target: cpu.register_pc }),
+                }
+                self.discard_popped(cpu, address);
+            }
+            Some(_) => self.discard_popped(cpu, address),
+        }
+    }
+
+    /// The calls in progress, outermost first.
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    /// The most recent warnings, oldest first.
+    pub fn warnings(&self) -> impl DoubleEndedIterator<Item = &StackWarning> {
+        self.warnings.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.warnings.clear();
+    }
+
+    // Drops the frames whose return address was pulled off the stack some other way. The stack
+    // may wrap, so a frame is gone when the pointer moved up to it by less than half the stack.
+    fn discard_popped<B: Bus>(&mut self, cpu: &Cpu<B>, address: u16) {
+        let remaining = self.frames.iter()
+            .rposition(|frame| (frame.stack_pointer.wrapping_sub(cpu.register_sp) as i8) > 0)
+            .map_or(0, |index| index + 1);
+        self.discard_above(remaining, address);
+    }
+
+    fn discard_above(&mut self, remaining: usize, address: u16) {
+        if self.frames.len() > remaining {
+            let count = self.frames.len() - remaining;
+            self.frames.truncate(remaining);
+            self.warn(StackWarning::DiscardedFrames { address, count });
+        }
+    }
+
+    fn warn(&mut self, warning: StackWarning) {
+        if self.warnings.len() == MAX_WARNINGS {
+            self.warnings.pop_front();
+        }
+        self.warnings.push_back(warning);
+    }
+}
+
+impl Display for StackWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackWarning::UnbalancedReturn { address, target } => {
+                write!(f, "${:04X}: return to ${:04X} without a matching call", address, target)
+            }
+            StackWarning::DiscardedFrames { address, count } => {
+                write!(f, "${:04X}: discarded {} frame(s) without returning", address, count)
+            }
+        }
+    }
+}
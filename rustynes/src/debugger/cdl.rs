@@ -0,0 +1,110 @@
+use crate::cpu::{AddressingMode, Cpu};
+use crate::cpu::cycle::{AccessKind, BusAccess};
+use crate::debugger::executed_opcode;
+use crate::memory::Bus;
+use crate::EmulationError;
+
+/// The byte was executed, as an opcode or an operand.
+pub const CDL_CODE: u8 = 0x01;
+/// The byte was read as data.
+pub const CDL_DATA: u8 = 0x02;
+/// The byte was jumped to through a pointer, such as by `JMP ($nnnn)`.
+pub const CDL_INDIRECT_CODE: u8 = 0x10;
+/// The byte was read through a pointer, such as by `LDA ($nn),Y`.
+pub const CDL_INDIRECT_DATA: u8 = 0x20;
+
+/// Marks which PRG ROM bytes were used as code or data, in the same `.cdl` format as FCEUX: a
+/// byte of flags for each PRG ROM byte, followed by one for each CHR ROM byte. Bits 2-3 of the
+/// PRG flags hold which 8KB window of $8000-$FFFF the byte was accessed through.
+#[derive(Debug, Clone)]
+pub struct CodeDataLogger {
+    prg: Vec<u8>,
+    chr: Vec<u8>,
+}
+
+impl CodeDataLogger {
+    pub fn new(prg_rom_size: usize, chr_rom_size: usize) -> CodeDataLogger {
+        CodeDataLogger {
+            prg: vec![0; prg_rom_size],
+            chr: vec![0; chr_rom_size],
+        }
+    }
+
+    /// Replaces the log with one saved by `to_bytes`, or by FCEUX, for a ROM of the same size.
+    pub fn load(&mut self, bytes: &[u8]) -> Result<(), EmulationError> {
+        if bytes.len() != self.prg.len() + self.chr.len() {
+            return Err(EmulationError::InvalidCodeDataLog);
+        }
+        let (prg, chr) = bytes.split_at(self.prg.len());
+        self.prg.copy_from_slice(prg);
+        self.chr.copy_from_slice(chr);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.prg.as_slice(), self.chr.as_slice()].concat()
+    }
+
+    /// Steps the CPU and marks the ROM bytes it used.
+    pub fn step<B: Bus>(&mut self, cpu: &mut Cpu<B>) -> Result<u8, EmulationError> {
+        if cpu.mid_instruction() {
+            return cpu.step();
+        }
+        let accesses = cpu.preview();
+        let address = cpu.register_pc;
+        let cycles = cpu.step()?;
+        self.record(cpu, address, &accesses);
+        Ok(cycles)
+    }
+
+    /// Marks the ROM bytes used by a step that started at `address` and made `accesses`.
+    pub(super) fn record<B: Bus>(&mut self, cpu: &Cpu<B>, address: u16, accesses: &[BusAccess]) {
+        let info = executed_opcode(accesses)
+            .and_then(|opcode| cpu.variant.opcodes()[opcode as usize]);
+        let length = info.map_or(0, |info| info.length as u16);
+        let indirect_data = info.is_some_and(|info| matches!(info.addressing_mode,
+            AddressingMode::IndirectX | AddressingMode::IndirectY | AddressingMode::ZeroPageIndirect));
+
+        for access in accesses {
+            let flags = match access.kind {
+                AccessKind::OpcodeFetch => CDL_CODE,
+                AccessKind::Read if access.address.wrapping_sub(address) < length => CDL_CODE,
+                // Those pointers are in zero page, so any ROM read is through them
+                AccessKind::Read if indirect_data => CDL_DATA | CDL_INDIRECT_DATA,
+                AccessKind::Read => CDL_DATA,
+                _ => continue,
+            };
+            self.mark(cpu, access.address, flags);
+        }
+
+        let indirect_jump = info.is_some_and(|info| info.mnemonic == "jmp" && matches!(info.addressing_mode,
+            AddressingMode::Indirect | AddressingMode::AbsoluteIndexedIndirect));
+        if indirect_jump {
+            self.mark(cpu, cpu.register_pc, CDL_INDIRECT_CODE);
+        }
+    }
+
+    /// The flags of the PRG ROM byte `address` maps to, if it maps to one.
+    pub fn flags<B: Bus>(&self, cpu: &Cpu<B>, address: u16) -> Option<u8> {
+        cpu.bus.prg_rom_offset(address).and_then(|offset| self.prg.get(offset).copied())
+    }
+
+    /// How many PRG ROM bytes were marked as code and as data.
+    pub fn coverage(&self) -> (usize, usize) {
+        let count = |flag| self.prg.iter().filter(|&&flags| flags & flag != 0).count();
+        (count(CDL_CODE), count(CDL_DATA))
+    }
+
+    pub fn clear(&mut self) {
+        self.prg.fill(0);
+        self.chr.fill(0);
+    }
+
+    fn mark<B: Bus>(&mut self, cpu: &Cpu<B>, address: u16, flags: u8) {
+        if let Some(offset) = cpu.bus.prg_rom_offset(address) {
+            if let Some(byte) = self.prg.get_mut(offset) {
+                *byte |= flags | ((address >> 11) & 0x0C) as u8;
+            }
+        }
+    }
+}
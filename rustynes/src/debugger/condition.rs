@@ -0,0 +1,282 @@
+use std::fmt::{Display, Formatter};
+use crate::cpu::Cpu;
+use crate::memory::Bus;
+use crate::EmulationError;
+
+/// A boolean expression over the CPU state, such as `A == $10 && [$0300] > 4`.
+///
+/// Values are registers (`A`, `X`, `Y`, `SP`, `PC`, `P`), flags (`N`, `V`, `D`, `I`, `Z`, `C`),
+/// numbers in decimal or hex with a `$` prefix, and memory reads as `[address]`. They can be
+/// compared with `==`, `!=`, `<`, `<=`, `>` and `>=`, negated with `!`, and combined with `&&` and
+/// `||`. Any non zero value is true.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    source: String,
+    expression: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Number(u32),
+    Register(Register),
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Register {
+    A,
+    X,
+    Y,
+    Sp,
+    Pc,
+    P,
+    Flag(u8),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(u32),
+    Identifier(String),
+    Operator(Operator),
+    Not,
+    OpenBracket,
+    CloseBracket,
+    OpenParen,
+    CloseParen,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, EmulationError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens: &tokens, position: 0, end: source.len() };
+        let expression = parser.parse_or()?;
+        if let Some((column, _)) = parser.peek() {
+            return Err(EmulationError::InvalidCondition(column, "expected end of condition".to_string()));
+        }
+        Ok(Condition {
+            source: source.trim().to_string(),
+            expression,
+        })
+    }
+
+    pub fn holds<B: Bus>(&self, cpu: &Cpu<B>) -> bool {
+        self.expression.evaluate(cpu) != 0
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Expression {
+    fn evaluate<B: Bus>(&self, cpu: &Cpu<B>) -> u32 {
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Register(register) => match register {
+                Register::A => cpu.register_a as u32,
+                Register::X => cpu.register_x as u32,
+                Register::Y => cpu.register_y as u32,
+                Register::Sp => cpu.register_sp as u32,
+                Register::Pc => cpu.register_pc as u32,
+                Register::P => cpu.status_flags.status as u32,
+                Register::Flag(mask) => (cpu.status_flags.status & mask != 0) as u32,
+            },
+            Expression::Memory(address) => {
+                cpu.bus.peek(address.evaluate(cpu) as u16).unwrap_or(0) as u32
+            }
+            Expression::Not(value) => (value.evaluate(cpu) == 0) as u32,
+            Expression::Binary(left, operator, right) => {
+                let left = left.evaluate(cpu);
+                // Short circuit so memory reads on the right only happen when needed
+                match operator {
+                    Operator::Or if left != 0 => return 1,
+                    Operator::And if left == 0 => return 0,
+                    _ => {}
+                }
+                let right = right.evaluate(cpu);
+                let result = match operator {
+                    Operator::Or | Operator::And => right != 0,
+                    Operator::Equal => left == right,
+                    Operator::NotEqual => left != right,
+                    Operator::Less => left < right,
+                    Operator::LessEqual => left <= right,
+                    Operator::Greater => left > right,
+                    Operator::GreaterEqual => left >= right,
+                };
+                result as u32
+            }
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, EmulationError> {
+    let mut tokens = Vec::new();
+    // Columns are byte offsets into the source, so they can slice it
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(source.len(), |(offset, _)| *offset);
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        let two = chars.get(i + 1).map(|(_, next)| [c, *next]);
+        let token = match (c, two) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            (_, Some(['|', '|'])) => Token::Operator(Operator::Or),
+            (_, Some(['&', '&'])) => Token::Operator(Operator::And),
+            (_, Some(['=', '='])) => Token::Operator(Operator::Equal),
+            (_, Some(['!', '='])) => Token::Operator(Operator::NotEqual),
+            (_, Some(['<', '='])) => Token::Operator(Operator::LessEqual),
+            (_, Some(['>', '='])) => Token::Operator(Operator::GreaterEqual),
+            ('<', _) => Token::Operator(Operator::Less),
+            ('>', _) => Token::Operator(Operator::Greater),
+            ('!', _) => Token::Not,
+            ('[', _) => Token::OpenBracket,
+            (']', _) => Token::CloseBracket,
+            ('(', _) => Token::OpenParen,
+            (')', _) => Token::CloseParen,
+            ('$', _) | ('0'..='9', _) => {
+                let (radix, digits_start) = if c == '$' { (16, i + 1) } else { (10, i) };
+                let mut end = digits_start;
+                while end < chars.len() && chars[end].1.is_ascii_alphanumeric() {
+                    end += 1;
+                }
+                let text = &source[start..offset(end)];
+                let value = u32::from_str_radix(&source[offset(digits_start)..offset(end)], radix).map_err(|_| {
+                    EmulationError::InvalidCondition(start, format!("invalid number `{}`", text))
+                })?;
+                tokens.push((start, Token::Number(value)));
+                i = end;
+                continue;
+            }
+            (c, _) if c.is_ascii_alphabetic() => {
+                let mut end = i;
+                while end < chars.len() && chars[end].1.is_ascii_alphanumeric() {
+                    end += 1;
+                }
+                tokens.push((start, Token::Identifier(source[start..offset(end)].to_string())));
+                i = end;
+                continue;
+            }
+            _ => return Err(EmulationError::InvalidCondition(start, format!("unexpected `{}`", c))),
+        };
+        i += match token {
+            Token::Operator(Operator::Less | Operator::Greater) | Token::Not => 1,
+            Token::Operator(_) => 2,
+            _ => 1,
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [(usize, Token)],
+    position: usize,
+    end: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens.get(self.position).map(|(column, token)| (*column, token))
+    }
+
+    fn next(&mut self) -> Result<(usize, &Token), EmulationError> {
+        let token = self.tokens.get(self.position)
+            .ok_or_else(|| EmulationError::InvalidCondition(self.end, "unexpected end of condition".to_string()))?;
+        self.position += 1;
+        Ok((token.0, &token.1))
+    }
+
+    fn expect(&mut self, expected: Token, description: &str) -> Result<(), EmulationError> {
+        let (column, token) = self.next()?;
+        if *token != expected {
+            return Err(EmulationError::InvalidCondition(column, format!("expected {}", description)));
+        }
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, EmulationError> {
+        let mut left = self.parse_and()?;
+        while let Some((_, Token::Operator(Operator::Or))) = self.peek() {
+            self.position += 1;
+            left = Expression::Binary(Box::new(left), Operator::Or, Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, EmulationError> {
+        let mut left = self.parse_comparison()?;
+        while let Some((_, Token::Operator(Operator::And))) = self.peek() {
+            self.position += 1;
+            left = Expression::Binary(Box::new(left), Operator::And, Box::new(self.parse_comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, EmulationError> {
+        let left = self.parse_unary()?;
+        match self.peek() {
+            Some((_, Token::Operator(operator))) if !matches!(operator, Operator::Or | Operator::And) => {
+                let operator = *operator;
+                self.position += 1;
+                Ok(Expression::Binary(Box::new(left), operator, Box::new(self.parse_unary()?)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, EmulationError> {
+        let (column, token) = self.next()?;
+        match token {
+            Token::Number(value) => Ok(Expression::Number(*value)),
+            Token::Identifier(name) => {
+                let register = match name.to_ascii_uppercase().as_str() {
+                    "A" => Register::A,
+                    "X" => Register::X,
+                    "Y" => Register::Y,
+                    "SP" => Register::Sp,
+                    "PC" => Register::Pc,
+                    "P" => Register::P,
+                    "N" => Register::Flag(0b1000_0000),
+                    "V" => Register::Flag(0b0100_0000),
+                    "D" => Register::Flag(0b0000_1000),
+                    "I" => Register::Flag(0b0000_0100),
+                    "Z" => Register::Flag(0b0000_0010),
+                    "C" => Register::Flag(0b0000_0001),
+                    _ => return Err(EmulationError::InvalidCondition(column, format!("unknown register `{}`", name))),
+                };
+                Ok(Expression::Register(register))
+            }
+            Token::Not => Ok(Expression::Not(Box::new(self.parse_unary()?))),
+            Token::OpenBracket => {
+                let address = self.parse_or()?;
+                self.expect(Token::CloseBracket, "`]`")?;
+                Ok(Expression::Memory(Box::new(address)))
+            }
+            Token::OpenParen => {
+                let expression = self.parse_or()?;
+                self.expect(Token::CloseParen, "`)`")?;
+                Ok(expression)
+            }
+            _ => Err(EmulationError::InvalidCondition(column, "expected a value".to_string())),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+use crate::cpu::Cpu;
+use crate::cpu::cycle::{AccessKind, BusAccess};
+use crate::cpu::state::CpuState;
+use crate::memory::Bus;
+use crate::EmulationError;
+
+pub const DEFAULT_HISTORY_LENGTH: usize = 10_000;
+
+/// A bounded record of the last instructions stepped, which can be undone one at a time.
+#[derive(Debug, Clone)]
+pub struct History {
+    capacity: usize,
+    entries: VecDeque<HistoryEntry>,
+}
+
+// The CPU state before an instruction and the values its writes replaced, in the order written.
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    state: CpuState,
+    writes: Vec<(u16, u8)>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            capacity,
+            entries: VecDeque::with_capacity(capacity.min(DEFAULT_HISTORY_LENGTH)),
+        }
+    }
+
+    /// Steps the CPU, remembering enough to undo the instruction. An instruction already started
+    /// by `tick` is finished without being recorded, since part of it has already happened.
+    pub fn step<B: Bus>(&mut self, cpu: &mut Cpu<B>) -> Result<u8, EmulationError> {
+        if self.capacity > 0 && !cpu.mid_instruction() && !cpu.halted {
+            let accesses = cpu.preview();
+            self.record(cpu, &accesses);
+        }
+        cpu.step()
+    }
+
+    /// Remembers the CPU state before a step that will make `accesses`.
+    pub(super) fn record<B: Bus>(&mut self, cpu: &Cpu<B>, accesses: &[BusAccess]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let state = cpu.snapshot();
+        let writes = accesses.iter()
+            .filter(|access| matches!(access.kind, AccessKind::Write | AccessKind::DummyWrite))
+            .map(|access| (access.address, cpu.bus.peek(access.address).unwrap_or(0)))
+            .collect();
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(HistoryEntry { state, writes });
+    }
+
+    /// Undoes the last instruction stepped, returning whether there was one.
+    pub fn step_back<B: Bus>(&mut self, cpu: &mut Cpu<B>) -> bool {
+        match self.entries.pop_back() {
+            Some(entry) => {
+                for &(address, value) in entry.writes.iter().rev() {
+                    let _ = cpu.bus.write(address, value);
+                }
+                cpu.restore(&entry.state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Steps back to just before the last remembered instruction that wrote to `address`,
+    /// returning whether there was one. The CPU is left alone when there wasn't.
+    pub fn back_to_last_write<B: Bus>(&mut self, cpu: &mut Cpu<B>, address: u16) -> bool {
+        let last_write = self.entries.iter()
+            .rposition(|entry| entry.writes.iter().any(|&(written, _)| written == address));
+        match last_write {
+            Some(index) => {
+                while self.entries.len() > index {
+                    self.step_back(cpu);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new(DEFAULT_HISTORY_LENGTH)
+    }
+}
@@ -0,0 +1,176 @@
+mod call_stack;
+mod cdl;
+mod condition;
+mod history;
+mod profiler;
+mod target;
+mod trace_diff;
+mod trace_logger;
+#[cfg(test)]
+mod test;
+
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+use crate::cpu::Cpu;
+use crate::cpu::cycle::{AccessKind, BusAccess};
+use crate::memory::Bus;
+use crate::EmulationError;
+
+pub use call_stack::{CallFrame, CallStack, StackWarning};
+pub use cdl::{CodeDataLogger, CDL_CODE, CDL_DATA, CDL_INDIRECT_CODE, CDL_INDIRECT_DATA};
+pub use condition::Condition;
+pub use history::{History, DEFAULT_HISTORY_LENGTH};
+pub use profiler::{Cost, Function, FunctionCost, Profiler};
+pub use target::RunTarget;
+pub use trace_diff::{diff_traces, parse_trace, TraceDivergence, TraceField, TraceLine};
+pub use trace_logger::{
+    read_binary_trace, TraceFilter, TraceFormat, TraceLimit, TraceLogger, TraceRecord, CYCLES_PER_FRAME,
+};
+
+/// Stops execution before the instruction at `address`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub condition: Option<Condition>,
+}
+
+/// Stops execution before an instruction that reads, writes or executes an address in `range`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub condition: Option<Condition>,
+}
+
+/// Why the debugger stopped execution.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    Watchpoint(BusAccess),
+    /// The run target was reached at the address.
+    Target(u16),
+}
+
+/// Decides when to stop a running CPU. It is checked between instructions, so it stops on the
+/// instruction that triggers it, before running it.
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Cleared once it is reached.
+    pub target: Option<RunTarget>,
+    /// Every step is recorded in it, so it can be undone.
+    pub history: History,
+    pub call_stack: CallStack,
+    pub profiler: Profiler,
+    /// Marks the ROM bytes used by every step when set.
+    pub code_data_logger: Option<CodeDataLogger>,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &BusAccess) -> bool {
+        let watched = match access.kind {
+            AccessKind::OpcodeFetch => self.execute,
+            AccessKind::Read => self.read,
+            AccessKind::Write => self.write,
+            // Dummy accesses only happen when ticking, so they would make stops depend on it
+            AccessKind::DummyRead | AccessKind::DummyWrite => false,
+        };
+        watched && self.range.contains(&access.address)
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    /// Steps the CPU and checks whether it should stop, either because the target was reached or
+    /// because the next instruction triggers a breakpoint or watchpoint.
+    pub fn step<B: Bus>(&mut self, cpu: &mut Cpu<B>) -> Result<Option<StopReason>, EmulationError> {
+        if cpu.mid_instruction() || cpu.halted {
+            cpu.step()?;
+            return Ok(self.check(cpu));
+        }
+        let accesses = cpu.preview();
+        let (address, stack_pointer) = (cpu.register_pc, cpu.register_sp);
+        self.history.record(cpu, &accesses);
+        let result = cpu.step();
+        let cycles = result.as_ref().copied().unwrap_or(0);
+        let instruction = executed_opcode(&accesses).is_some();
+        // Instructions count towards the calls they ran in, interrupts towards their handler
+        if instruction {
+            self.profiler.record(address, cycles, true, self.call_stack.frames());
+        }
+        self.call_stack.update(cpu, address, stack_pointer, &accesses);
+        if !instruction {
+            self.profiler.record(cpu.register_pc, cycles, false, self.call_stack.frames());
+        }
+        result?;
+        if let Some(code_data_logger) = &mut self.code_data_logger {
+            code_data_logger.record(cpu, address, &accesses);
+        }
+        if let Some(target) = self.target {
+            if target.reached(cpu, target::returns(&accesses)) {
+                self.target = None;
+                return Ok(Some(StopReason::Target(cpu.register_pc)));
+            }
+        }
+        Ok(self.check(cpu))
+    }
+
+    /// Checks whether the next instruction should stop execution.
+    pub fn check<B: Bus>(&self, cpu: &mut Cpu<B>) -> Option<StopReason> {
+        if cpu.halted {
+            return None;
+        }
+        let holds = |condition: &Option<Condition>, cpu: &Cpu<B>| {
+            condition.as_ref().is_none_or(|condition| condition.holds(cpu))
+        };
+
+        for breakpoint in &self.breakpoints {
+            if breakpoint.address == cpu.register_pc && holds(&breakpoint.condition, cpu) {
+                return Some(StopReason::Breakpoint(breakpoint.address));
+            }
+        }
+
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+        let accesses = cpu.preview();
+        for watchpoint in &self.watchpoints {
+            if let Some(access) = accesses.iter().find(|access| watchpoint.matches(access)) {
+                if holds(&watchpoint.condition, cpu) {
+                    return Some(StopReason::Watchpoint(*access));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Breakpoint(address) => write!(f, "Breakpoint at ${:04X}", address),
+            StopReason::Target(address) => write!(f, "Stopped at ${:04X}", address),
+            StopReason::Watchpoint(access) => {
+                let kind = match access.kind {
+                    AccessKind::OpcodeFetch => "Execute",
+                    AccessKind::Read | AccessKind::DummyRead => "Read",
+                    AccessKind::Write | AccessKind::DummyWrite => "Write",
+                };
+                write!(f, "{} of ${:04X} = ${:02X}", kind, access.address, access.value)
+            }
+        }
+    }
+}
+
+/// The opcode of the instruction that made `accesses`, or `None` if they serviced an interrupt.
+fn executed_opcode(accesses: &[BusAccess]) -> Option<u8> {
+    accesses.first()
+        .filter(|access| access.kind == AccessKind::OpcodeFetch)
+        .map(|access| access.value)
+}
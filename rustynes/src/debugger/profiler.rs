@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::ops::AddAssign;
+use crate::debugger::CallFrame;
+
+/// What it cost to run some code.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Cost {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+/// The code a cost is attributed to, named after where it was entered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Function {
+    /// Code that isn't in any call the debugger saw, such as the main loop.
+    Main,
+    Subroutine(u16),
+    Interrupt(u16),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FunctionCost {
+    pub function: Function,
+    /// The cost of the function's own instructions.
+    pub self_cost: Cost,
+    /// The cost including everything the function called.
+    pub total_cost: Cost,
+}
+
+/// Counts the instructions and cycles spent at each address, and in each call stack they ran in.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    /// Steps are only counted while this is set.
+    pub enabled: bool,
+    addresses: HashMap<u16, Cost>,
+    stacks: HashMap<Vec<Function>, Cost>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// Counts a step at `address` made inside `frames`. Servicing an interrupt takes cycles
+    /// without running an instruction.
+    pub(super) fn record(&mut self, address: u16, cycles: u8, instruction: bool, frames: &[CallFrame]) {
+        if !self.enabled {
+            return;
+        }
+        let cost = Cost {
+            instructions: instruction as u64,
+            cycles: cycles as u64,
+        };
+        *self.addresses.entry(address).or_default() += cost;
+        let stack = frames.iter()
+            .map(|frame| if frame.interrupt {
+                Function::Interrupt(frame.target)
+            } else {
+                Function::Subroutine(frame.target)
+            })
+            .collect();
+        *self.stacks.entry(stack).or_default() += cost;
+    }
+
+    /// The cost of each address that ran, most cycles first.
+    pub fn addresses(&self) -> Vec<(u16, Cost)> {
+        let mut addresses: Vec<(u16, Cost)> = self.addresses.iter().map(|(&address, &cost)| (address, cost)).collect();
+        addresses.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        addresses
+    }
+
+    /// The cost of each function that ran, most cycles first.
+    pub fn functions(&self) -> Vec<FunctionCost> {
+        let mut functions: HashMap<Function, FunctionCost> = HashMap::new();
+        for (stack, &cost) in &self.stacks {
+            let innermost = stack.last().copied().unwrap_or(Function::Main);
+            function_cost(&mut functions, innermost).self_cost += cost;
+            // Recursive functions appear more than once, but only count once towards their total
+            let mut seen = Vec::new();
+            for &function in std::iter::once(&Function::Main).chain(stack) {
+                if !seen.contains(&function) {
+                    seen.push(function);
+                    function_cost(&mut functions, function).total_cost += cost;
+                }
+            }
+        }
+        let mut functions: Vec<FunctionCost> = functions.into_values().collect();
+        functions.sort_by(|a, b| b.self_cost.cycles.cmp(&a.self_cost.cycles).then(a.function.cmp(&b.function)));
+        functions
+    }
+
+    /// Exports the cycles spent in each call stack in the collapsed format flamegraph tools read,
+    /// with one `main;sub_8010;sub_8040 123` line per stack.
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines: Vec<String> = self.stacks.iter()
+            .filter(|(_, cost)| cost.cycles > 0)
+            .map(|(stack, cost)| {
+                let names: Vec<String> = std::iter::once(&Function::Main).chain(stack)
+                    .map(|function| function.to_string())
+                    .collect();
+                format!("{} {}", names.join(";"), cost.cycles)
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.addresses.clear();
+        self.stacks.clear();
+    }
+}
+
+fn function_cost(functions: &mut HashMap<Function, FunctionCost>, function: Function) -> &mut FunctionCost {
+    functions.entry(function).or_insert(FunctionCost {
+        function,
+        self_cost: Cost::default(),
+        total_cost: Cost::default(),
+    })
+}
+
+impl AddAssign for Cost {
+    fn add_assign(&mut self, other: Cost) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Function::Main => write!(f, "main"),
+            Function::Subroutine(address) => write!(f, "sub_{:04X}", address),
+            Function::Interrupt(address) => write!(f, "int_{:04X}", address),
+        }
+    }
+}
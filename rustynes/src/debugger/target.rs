@@ -0,0 +1,61 @@
+use crate::cpu::Cpu;
+use crate::cpu::cycle::BusAccess;
+use crate::debugger::executed_opcode;
+use crate::memory::Bus;
+
+const JSR: u8 = 0x20;
+const RTI: u8 = 0x40;
+const RTS: u8 = 0x60;
+
+/// Where a run started by the debugger stops on its own. Subroutines are followed with the stack
+/// pointer, so recursion and interrupts in between don't stop it early.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RunTarget {
+    /// Stops after a single instruction.
+    Step,
+    /// Stops once the JSR that was at PC has returned.
+    StepOver { return_address: u16, stack_pointer: u8 },
+    /// Stops once an RTS or RTI returns from the subroutine or interrupt that was running.
+    StepOut { stack_pointer: u8 },
+    /// Stops before the instruction at the address.
+    RunTo(u16),
+}
+
+impl RunTarget {
+    /// Steps over the instruction at PC, running the whole subroutine if it is a JSR.
+    pub fn step_over<B: Bus>(cpu: &Cpu<B>) -> RunTarget {
+        match cpu.bus.peek(cpu.register_pc) {
+            Ok(JSR) => RunTarget::StepOver {
+                return_address: cpu.register_pc.wrapping_add(3),
+                stack_pointer: cpu.register_sp,
+            },
+            _ => RunTarget::Step,
+        }
+    }
+
+    pub fn step_out<B: Bus>(cpu: &Cpu<B>) -> RunTarget {
+        RunTarget::StepOut { stack_pointer: cpu.register_sp }
+    }
+
+    /// Whether the CPU reached the target with its last step. `returned` tells if that step ran
+    /// an RTS or RTI.
+    pub(super) fn reached<B: Bus>(&self, cpu: &Cpu<B>, returned: bool) -> bool {
+        match *self {
+            RunTarget::Step => true,
+            RunTarget::StepOver { return_address, stack_pointer } => {
+                cpu.register_pc == return_address && cpu.register_sp == stack_pointer
+            }
+            // The stack may wrap, so a frame is popped when the pointer moved up by less than half
+            RunTarget::StepOut { stack_pointer } => {
+                returned && (cpu.register_sp.wrapping_sub(stack_pointer) as i8) > 0
+            }
+            RunTarget::RunTo(address) => cpu.register_pc == address,
+        }
+    }
+}
+
+/// Whether a step that made `accesses` ran an RTS or RTI, rather than another instruction or an
+/// interrupt.
+pub(super) fn returns(accesses: &[BusAccess]) -> bool {
+    matches!(executed_opcode(accesses), Some(RTS | RTI))
+}
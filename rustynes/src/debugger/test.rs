@@ -0,0 +1,457 @@
+use std::fs;
+use crate::cpu::Cpu;
+use crate::cpu::cycle::AccessKind;
+use crate::debugger::{
+    Breakpoint, CallFrame, CallStack, CodeDataLogger, Condition, Cost, Debugger, Function, FunctionCost, History,
+    RunTarget, StackWarning, StopReason, TraceField, TraceFilter, TraceFormat, TraceLimit, TraceLine, TraceLogger,
+    Watchpoint, CDL_CODE, CDL_DATA, CDL_INDIRECT_CODE, CDL_INDIRECT_DATA, diff_traces, read_binary_trace,
+};
+use crate::memory::Bus;
+use crate::memory::nes::NesBus;
+use crate::memory::test_game::TestGameBus;
+use crate::rom::Rom;
+use crate::EmulationError;
+
+fn program_cpu(program: &[u8]) -> Cpu<TestGameBus> {
+    let mut cpu = Cpu::new(TestGameBus::new());
+    for (i, byte) in program.iter().enumerate() {
+        cpu.bus.write(0x8000 + i as u16, *byte).unwrap();
+    }
+    cpu.register_pc = 0x8000;
+    cpu
+}
+
+// Steps until the debugger stops, returning the stop and the PC it stopped on.
+fn run(debugger: &Debugger, cpu: &mut Cpu<TestGameBus>, max_steps: usize) -> Option<(StopReason, u16)> {
+    for _ in 0..max_steps {
+        cpu.step().unwrap();
+        if let Some(reason) = debugger.check(cpu) {
+            return Some((reason, cpu.register_pc));
+        }
+    }
+    None
+}
+
+#[test]
+fn test_conditions() {
+    let mut cpu = program_cpu(&[]);
+    cpu.register_a = 0x10;
+    cpu.register_x = 0x03;
+    cpu.bus.write(0x0300, 0x05).unwrap();
+    cpu.bus.write(0x0010, 0x03).unwrap();
+
+    let holds = |source: &str| Condition::parse(source).unwrap().holds(&cpu);
+    assert!(holds("A == $10 && [$0300] > 4"));
+    assert!(!holds("A == $10 && [$0300] > 5"));
+    assert!(holds("a != 16 || x == 3"));
+    assert!(holds("[A] == X"));
+    assert!(holds("[$02FD] == 1 || ([$0300] >= 5 && !(PC < $8000))"));
+    assert!(holds("I && !C && Z == 0"));
+    assert_eq!(Condition::parse("  A == $10 ").unwrap().to_string(), "A == $10");
+}
+
+#[test]
+fn test_condition_errors() {
+    let error = |source: &str| match Condition::parse(source) {
+        Err(EmulationError::InvalidCondition(column, message)) => (column, message),
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(error("A == "), (5, "unexpected end of condition".to_string()));
+    assert_eq!(error("Q == 1"), (0, "unknown register `Q`".to_string()));
+    assert_eq!(error("[$0300 > 4"), (10, "unexpected end of condition".to_string()));
+    assert_eq!(error("A == $1G"), (5, "invalid number `$1G`".to_string()));
+    assert_eq!(error("A == 1 B"), (7, "expected end of condition".to_string()));
+    assert_eq!(error("A = 1"), (2, "unexpected `=`".to_string()));
+    // Columns are byte offsets, so multibyte characters count for more than one
+    assert_eq!(error("\u{a0}$1G"), (2, "invalid number `$1G`".to_string()));
+    assert_eq!(error("A == \u{a0}"), (7, "unexpected end of condition".to_string()));
+    assert_eq!(error("A == é"), (5, "unexpected `é`".to_string()));
+}
+
+#[test]
+fn test_breakpoints() {
+    // LDX #$00; loop: INX; CPX #$10; BNE loop; NOP
+    let mut cpu = program_cpu(&[0xA2, 0x00, 0xE8, 0xE0, 0x10, 0xD0, 0xFB, 0xEA]);
+    let mut debugger = Debugger::new();
+    debugger.breakpoints.push(Breakpoint {
+        address: 0x8002,
+        condition: Some(Condition::parse("X == 5").unwrap()),
+    });
+    // The breakpoint stops before the INX, leaving X untouched
+    assert_eq!(run(&debugger, &mut cpu, 100), Some((StopReason::Breakpoint(0x8002), 0x8002)));
+    assert_eq!(cpu.register_x, 5);
+
+    debugger.breakpoints.push(Breakpoint { address: 0x8007, condition: None });
+    assert_eq!(run(&debugger, &mut cpu, 100), Some((StopReason::Breakpoint(0x8007), 0x8007)));
+    assert_eq!(cpu.register_x, 0x10);
+}
+
+#[test]
+fn test_watchpoints() {
+    // LDA #$42; STA $0310; LDA $0310; JMP $0400
+    let mut cpu = program_cpu(&[0xA9, 0x42, 0x8D, 0x10, 0x03, 0xAD, 0x10, 0x03, 0x4C, 0x00, 0x04]);
+    let mut debugger = Debugger::new();
+    debugger.watchpoints.push(Watchpoint {
+        range: 0x0300..=0x03FF,
+        read: false,
+        write: true,
+        execute: false,
+        condition: None,
+    });
+    debugger.watchpoints.push(Watchpoint {
+        range: 0x0310..=0x0310,
+        read: true,
+        write: false,
+        execute: false,
+        condition: Some(Condition::parse("A == $42").unwrap()),
+    });
+    debugger.watchpoints.push(Watchpoint {
+        range: 0x0400..=0x04FF,
+        read: false,
+        write: false,
+        execute: true,
+        condition: None,
+    });
+
+    // The write is caught before it happens
+    let (reason, pc) = run(&debugger, &mut cpu, 10).unwrap();
+    assert_eq!(pc, 0x8002);
+    assert!(matches!(reason, StopReason::Watchpoint(access)
+        if access.address == 0x0310 && access.value == 0x42 && access.kind == AccessKind::Write));
+    assert_eq!(cpu.bus.peek(0x0310).unwrap(), 0x00);
+
+    let (reason, pc) = run(&debugger, &mut cpu, 10).unwrap();
+    assert_eq!(pc, 0x8005);
+    assert_eq!(reason.to_string(), "Read of $0310 = $42");
+
+    let (reason, pc) = run(&debugger, &mut cpu, 10).unwrap();
+    assert_eq!(pc, 0x0400);
+    assert_eq!(reason.to_string(), "Execute of $0400 = $00");
+}
+
+// Steps through the debugger until it stops, returning the stop and the PC it stopped on.
+fn step_until(debugger: &mut Debugger, cpu: &mut Cpu<TestGameBus>, max_steps: usize) -> Option<(StopReason, u16)> {
+    for _ in 0..max_steps {
+        if let Some(reason) = debugger.step(cpu).unwrap() {
+            return Some((reason, cpu.register_pc));
+        }
+    }
+    None
+}
+
+#[test]
+fn test_run_targets() {
+    let mut cpu = program_cpu(&[]);
+    // JSR $8010; NOP; JMP $8004
+    let main = [0x20, 0x10, 0x80, 0xEA, 0x4C, 0x04, 0x80];
+    // JSR $8020; PLA; PHA; RTS
+    let outer = [0x20, 0x20, 0x80, 0x68, 0x48, 0x60];
+    // LDA #$01; RTS
+    let inner = [0xA9, 0x01, 0x60];
+    for (start, bytes) in [(0x8000, &main[..]), (0x8010, &outer[..]), (0x8020, &inner[..])] {
+        for (i, byte) in bytes.iter().enumerate() {
+            cpu.bus.write(start + i as u16, *byte).unwrap();
+        }
+    }
+    let mut debugger = Debugger::new();
+
+    debugger.target = Some(RunTarget::step_over(&cpu));
+    assert_eq!(step_until(&mut debugger, &mut cpu, 20), Some((StopReason::Target(0x8003), 0x8003)));
+    assert_eq!(debugger.target, None);
+    assert_eq!(RunTarget::step_over(&cpu), RunTarget::Step);
+
+    // Breakpoints inside the subroutine still stop a step over
+    cpu.register_pc = 0x8000;
+    debugger.breakpoints.push(Breakpoint { address: 0x8020, condition: None });
+    debugger.target = Some(RunTarget::step_over(&cpu));
+    assert_eq!(step_until(&mut debugger, &mut cpu, 20), Some((StopReason::Breakpoint(0x8020), 0x8020)));
+    debugger.breakpoints.clear();
+
+    // The PLA moves the stack pointer above the frame, but only the RTS returns from it
+    debugger.target = Some(RunTarget::step_out(&cpu));
+    assert_eq!(step_until(&mut debugger, &mut cpu, 20), Some((StopReason::Target(0x8013), 0x8013)));
+    debugger.target = Some(RunTarget::step_out(&cpu));
+    assert_eq!(step_until(&mut debugger, &mut cpu, 20), Some((StopReason::Target(0x8003), 0x8003)));
+
+    debugger.target = Some(RunTarget::RunTo(0x8004));
+    assert_eq!(step_until(&mut debugger, &mut cpu, 20), Some((StopReason::Target(0x8004), 0x8004)));
+    assert_eq!(StopReason::Target(0x8004).to_string(), "Stopped at $8004");
+}
+
+#[test]
+fn test_history() {
+    // LDA #$01; STA $0300; INC $0300; LDX #$07; STX $0301; NOP
+    let mut cpu = program_cpu(&[0xA9, 0x01, 0x8D, 0x00, 0x03, 0xEE, 0x00, 0x03, 0xA2, 0x07, 0x8E, 0x01, 0x03, 0xEA]);
+    let start = cpu.snapshot();
+    let mut history = History::new(10);
+    for _ in 0..6 {
+        history.step(&mut cpu).unwrap();
+    }
+    assert_eq!(history.len(), 6);
+    assert_eq!(cpu.bus.peek(0x0300).unwrap(), 0x02);
+
+    assert!(history.step_back(&mut cpu));
+    assert_eq!(cpu.register_pc, 0x800D);
+    assert!(history.back_to_last_write(&mut cpu, 0x0300));
+    assert_eq!(cpu.register_pc, 0x8005);
+    assert_eq!(cpu.bus.peek(0x0300).unwrap(), 0x01);
+    assert_eq!(cpu.bus.peek(0x0301).unwrap(), 0x00);
+    assert_eq!(cpu.register_x, 0x00);
+    assert!(!history.back_to_last_write(&mut cpu, 0x0400));
+    assert_eq!(history.len(), 2);
+
+    while history.step_back(&mut cpu) {}
+    assert_eq!(cpu.snapshot(), start);
+    assert_eq!(cpu.bus.peek(0x0300).unwrap(), 0x00);
+
+    // Only the most recent instructions are kept
+    let mut history = History::new(2);
+    for _ in 0..4 {
+        history.step(&mut cpu).unwrap();
+    }
+    assert!(history.step_back(&mut cpu) && history.step_back(&mut cpu));
+    assert!(!history.step_back(&mut cpu));
+    assert_eq!(cpu.register_pc, 0x8005);
+}
+
+#[test]
+fn test_call_stack() {
+    let mut cpu = program_cpu(&[]);
+    let routines: [(u16, &[u8]); 4] = [
+        // JSR $8010; BRK; NOP
+        (0x8000, &[0x20, 0x10, 0x80, 0x00, 0x00, 0xEA]),
+        // Jumps to $8020 through the stack: LDA #$80; PHA; LDA #$1F; PHA; RTS
+        (0x8010, &[0xA9, 0x80, 0x48, 0xA9, 0x1F, 0x48, 0x60]),
+        // JSR $8030
+        (0x8020, &[0x20, 0x30, 0x80]),
+        // Drops its own return address: PLA; PLA; RTS
+        (0x8030, &[0x68, 0x68, 0x60]),
+    ];
+    for (start, bytes) in routines {
+        for (i, byte) in bytes.iter().enumerate() {
+            cpu.bus.write(start + i as u16, *byte).unwrap();
+        }
+    }
+    // The BRK handler is a lone RTI
+    cpu.bus.write(0x8040, 0x40).unwrap();
+    cpu.bus.write_word(0xFFFE, 0x8040).unwrap();
+    let stack_pointer = cpu.register_sp;
+    let mut call_stack = CallStack::new();
+
+    call_stack.step(&mut cpu).unwrap();
+    assert_eq!(call_stack.frames(), &[CallFrame {
+        caller: 0x8000,
+        target: 0x8010,
+        return_address: 0x8003,
+        stack_pointer,
+        interrupt: false,
+    }]);
+
+    for _ in 0..5 {
+        call_stack.step(&mut cpu).unwrap();
+    }
+    assert_eq!(cpu.register_pc, 0x8020);
+    assert_eq!(call_stack.frames().len(), 1);
+
+    // The PLAs discard the inner frame, so its RTS returns from the outer one
+    for _ in 0..4 {
+        call_stack.step(&mut cpu).unwrap();
+    }
+    assert_eq!(cpu.register_pc, 0x8003);
+    assert!(call_stack.frames().is_empty());
+    assert_eq!(call_stack.warnings().copied().collect::<Vec<_>>(), vec![
+        StackWarning::UnbalancedReturn { address: 0x8016, target: 0x8020 },
+        StackWarning::DiscardedFrames { address: 0x8031, count: 1 },
+    ]);
+    assert_eq!(call_stack.warnings().next().unwrap().to_string(), "$8016: return to $8020 without a matching call");
+
+    call_stack.step(&mut cpu).unwrap();
+    assert_eq!(call_stack.frames(), &[CallFrame {
+        caller: 0x8003,
+        target: 0x8040,
+        return_address: 0x8005,
+        stack_pointer,
+        interrupt: true,
+    }]);
+    call_stack.step(&mut cpu).unwrap();
+    assert_eq!(cpu.register_pc, 0x8005);
+    assert!(call_stack.frames().is_empty());
+    assert_eq!(call_stack.warnings().count(), 2);
+}
+
+#[test]
+fn test_profiler() {
+    let mut cpu = program_cpu(&[]);
+    let routines: [(u16, &[u8]); 3] = [
+        // JSR $8010; JMP $8000
+        (0x8000, &[0x20, 0x10, 0x80, 0x4C, 0x00, 0x80]),
+        // JSR $8020; RTS
+        (0x8010, &[0x20, 0x20, 0x80, 0x60]),
+        // NOP; RTS
+        (0x8020, &[0xEA, 0x60]),
+    ];
+    for (start, bytes) in routines {
+        for (i, byte) in bytes.iter().enumerate() {
+            cpu.bus.write(start + i as u16, *byte).unwrap();
+        }
+    }
+    let mut debugger = Debugger::new();
+    debugger.profiler.enabled = true;
+    // Two runs through the loop
+    for _ in 0..12 {
+        debugger.step(&mut cpu).unwrap();
+    }
+
+    assert_eq!(debugger.profiler.collapsed_stacks(), "main 18\nmain;sub_8010 24\nmain;sub_8010;sub_8020 16\n");
+    assert_eq!(debugger.profiler.addresses()[0], (0x8000, Cost { instructions: 2, cycles: 12 }));
+    assert_eq!(debugger.profiler.functions(), vec![
+        FunctionCost {
+            function: Function::Subroutine(0x8010),
+            self_cost: Cost { instructions: 4, cycles: 24 },
+            total_cost: Cost { instructions: 8, cycles: 40 },
+        },
+        FunctionCost {
+            function: Function::Main,
+            self_cost: Cost { instructions: 4, cycles: 18 },
+            total_cost: Cost { instructions: 12, cycles: 58 },
+        },
+        FunctionCost {
+            function: Function::Subroutine(0x8020),
+            self_cost: Cost { instructions: 4, cycles: 16 },
+            total_cost: Cost { instructions: 4, cycles: 16 },
+        },
+    ]);
+
+    debugger.profiler.enabled = false;
+    debugger.step(&mut cpu).unwrap();
+    assert_eq!(debugger.profiler.addresses()[0].1.instructions, 2);
+}
+
+#[test]
+fn test_code_data_logger() {
+    let mut raw = vec![0; 16 + 0x4000];
+    raw[0..4].copy_from_slice(b"NES\x1A");
+    raw[4] = 1;
+    // LDA $8010; LDA ($00),Y; JMP ($8012)
+    raw[16..24].copy_from_slice(&[0xAD, 0x10, 0x80, 0xB1, 0x00, 0x6C, 0x12, 0x80]);
+    // Pointer to $C020, in the mirror of the same 16KB
+    raw[16 + 0x12..16 + 0x14].copy_from_slice(&[0x20, 0xC0]);
+    raw[16 + 0x20] = 0xEA;
+    let rom = Rom::new(&raw).unwrap();
+    let mut code_data_logger = CodeDataLogger::new(rom.prg_rom_size(), rom.chr_rom_size());
+    let mut cpu = Cpu::new(NesBus::new(rom));
+    cpu.bus.write_word(0x0000, 0x8011).unwrap();
+    cpu.register_pc = 0x8000;
+    for _ in 0..4 {
+        code_data_logger.step(&mut cpu).unwrap();
+    }
+
+    let bytes = code_data_logger.to_bytes();
+    assert_eq!(bytes.len(), 0x4000);
+    assert_eq!(&bytes[0..9], &[CDL_CODE, CDL_CODE, CDL_CODE, CDL_CODE, CDL_CODE, CDL_CODE, CDL_CODE, CDL_CODE, 0]);
+    assert_eq!(bytes[0x10], CDL_DATA);
+    assert_eq!(bytes[0x11], CDL_DATA | CDL_INDIRECT_DATA);
+    assert_eq!(&bytes[0x12..0x14], &[CDL_DATA, CDL_DATA]);
+    // Marked with the $C000-$DFFF window it ran from
+    assert_eq!(bytes[0x20], CDL_CODE | CDL_INDIRECT_CODE | 0x08);
+    assert_eq!(code_data_logger.coverage(), (9, 4));
+    assert_eq!(code_data_logger.flags(&cpu, 0xC010), Some(CDL_DATA));
+    assert_eq!(code_data_logger.flags(&cpu, 0x0010), None);
+
+    let mut loaded = CodeDataLogger::new(0x4000, 0);
+    assert!(matches!(loaded.load(&bytes[1..]), Err(EmulationError::InvalidCodeDataLog)));
+    loaded.load(&bytes).unwrap();
+    assert_eq!(loaded.to_bytes(), bytes);
+}
+
+#[test]
+fn test_trace_logger() {
+    // LDX #$00; INX; JMP $8002
+    let program = [0xA2, 0x00, 0xE8, 0x4C, 0x02, 0x80];
+    let dir = std::env::temp_dir().join(format!("rustynes_trace_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("mesen.log");
+    let mut cpu = program_cpu(&program);
+    let mut trace_logger = TraceLogger::create(&path, TraceFormat::Mesen, TraceLimit::Unlimited).unwrap();
+    trace_logger.filter = TraceFilter {
+        pc_range: Some(0x8002..=0x8002),
+        frame_range: Some(0..=0),
+        trigger: Some(Condition::parse("X == 2").unwrap()),
+    };
+    for _ in 0..11 {
+        trace_logger.log(&cpu).unwrap();
+        cpu.step().unwrap();
+    }
+    drop(trace_logger);
+    let log = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    // Still logged once X moves past the trigger
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "8002  INX                              A:00 X:02 Y:00 S:FD P:nvUbdIzc Cycle:19");
+
+    let path = dir.join("fceux.log");
+    let mut cpu = program_cpu(&program);
+    let mut trace_logger = TraceLogger::create(&path, TraceFormat::Fceux, TraceLimit::Stop(1)).unwrap();
+    for _ in 0..3 {
+        trace_logger.log(&cpu).unwrap();
+        cpu.step().unwrap();
+    }
+    drop(trace_logger);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "A:00 X:00 Y:00 S:FD P:nvUbdIzc  $8000:A2 00     LDX #$00\n");
+
+    let path = dir.join("binary.log");
+    let mut cpu = program_cpu(&program);
+    // Room for the header and four records
+    let mut trace_logger = TraceLogger::create(&path, TraceFormat::Binary, TraceLimit::Ring(5 + 19 * 4)).unwrap();
+    for _ in 0..6 {
+        trace_logger.log(&cpu).unwrap();
+        cpu.step().unwrap();
+    }
+    drop(trace_logger);
+    let mut rotated = path.clone().into_os_string();
+    rotated.push(".1");
+    let older = read_binary_trace(&fs::read(rotated).unwrap()).unwrap();
+    let newer = read_binary_trace(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(older.len(), 4);
+    assert_eq!(newer.len(), 2);
+    assert_eq!(older[0].address, 0x8000);
+    assert_eq!(older[0].bytes, [0xA2, 0x00, 0x00]);
+    assert_eq!((newer[0].address, newer[0].register_x, newer[0].length), (0x8003, 2, 3));
+    assert!(matches!(read_binary_trace(b"6TRC\x01\x00"), Err(EmulationError::InvalidTraceLog)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_trace_diff() {
+    let expected = "\
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10
+C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 36 CYC:12
+C5F9  86 10     STX $10 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 45 CYC:15
+";
+    let line = TraceLine::parse(1, expected.lines().next().unwrap()).unwrap();
+    assert_eq!((line.pc, line.opcode, line.p, line.sp, line.cycles), (0xC000, 0x4C, Some(0x24), Some(0xFD), Some(7)));
+    assert_eq!(TraceLine::parse(1, ""), None);
+
+    // Without the PPU and CYC columns, and starting a line later
+    let actual = "\
+C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD
+C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD
+C5F9  86 10     STX $10 = 00                    A:00 X:00 Y:00 P:24 SP:FD
+";
+    let divergence = diff_traces(expected, actual, 1).unwrap();
+    assert_eq!(divergence.field, TraceField::P);
+    assert_eq!(divergence.expected.as_ref().unwrap().number, 4);
+    assert_eq!(divergence.actual.as_ref().unwrap().number, 3);
+    assert_eq!(divergence.before.len(), 1);
+    assert_eq!(divergence.before[0].pc, 0xC5F7);
+    assert!(divergence.to_string().starts_with("Traces diverge at expected line 4, actual line 3: P is $24 instead of $26\n"));
+
+    let truncated = expected.lines().take(3).collect::<Vec<_>>().join("\n");
+    let divergence = diff_traces(expected, &truncated, 0).unwrap();
+    assert_eq!(divergence.field, TraceField::Length);
+    assert_eq!(divergence.actual, None);
+    assert_eq!(diff_traces(expected, expected, 5), None);
+}
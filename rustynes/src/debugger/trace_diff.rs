@@ -0,0 +1,204 @@
+use std::fmt::{Display, Formatter};
+
+/// A field of a trace line that two traces can disagree on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceField {
+    Pc,
+    Opcode,
+    A,
+    X,
+    Y,
+    P,
+    Sp,
+    Cycles,
+    /// One trace ended before the other.
+    Length,
+}
+
+/// The parts of a line in the `Trace` display format that are compared. Registers and cycles are
+/// `None` when the column is missing, and are then not compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceLine {
+    /// Line number in the file, starting at 1.
+    pub number: usize,
+    pub text: String,
+    pub pc: u16,
+    pub opcode: u8,
+    pub a: Option<u8>,
+    pub x: Option<u8>,
+    pub y: Option<u8>,
+    pub p: Option<u8>,
+    pub sp: Option<u8>,
+    pub cycles: Option<u64>,
+}
+
+/// Where two traces first disagree, with the lines around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceDivergence {
+    pub field: TraceField,
+    /// The line of each trace that disagrees, or `None` past the end of a trace.
+    pub expected: Option<TraceLine>,
+    pub actual: Option<TraceLine>,
+    /// Matching lines before the divergence, oldest first.
+    pub before: Vec<TraceLine>,
+    /// Lines of each trace after the divergence.
+    pub expected_after: Vec<TraceLine>,
+    pub actual_after: Vec<TraceLine>,
+}
+
+impl TraceLine {
+    /// Parses a line such as `C000  4C F5 C5  JMP $C5F5  A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`.
+    /// Lines that don't start with a PC and an opcode, such as blank lines, give `None`.
+    pub fn parse(number: usize, text: &str) -> Option<TraceLine> {
+        let text = text.trim_end();
+        let mut words = text.split_whitespace();
+        let pc = words.next().filter(|pc| pc.len() == 4)?;
+        let opcode = words.next().filter(|opcode| opcode.len() == 2)?;
+        let column = |name: &str| text.split_whitespace()
+            .find_map(|word| word.strip_prefix(name))
+            .filter(|value| !value.is_empty());
+        let register = |name: &str| column(name).and_then(|value| u8::from_str_radix(value, 16).ok());
+        Some(TraceLine {
+            number,
+            text: text.to_string(),
+            pc: u16::from_str_radix(pc, 16).ok()?,
+            opcode: u8::from_str_radix(opcode, 16).ok()?,
+            a: register("A:"),
+            x: register("X:"),
+            y: register("Y:"),
+            p: register("P:"),
+            sp: register("SP:"),
+            cycles: column("CYC:").and_then(|value| value.parse().ok()),
+        })
+    }
+
+    // The first field this line and `other` disagree on, skipping columns either is missing.
+    fn first_difference(&self, other: &TraceLine) -> Option<TraceField> {
+        fn differs<T: PartialEq>(expected: Option<T>, actual: Option<T>) -> bool {
+            matches!((expected, actual), (Some(expected), Some(actual)) if expected != actual)
+        }
+        if self.pc != other.pc {
+            Some(TraceField::Pc)
+        } else if self.opcode != other.opcode {
+            Some(TraceField::Opcode)
+        } else if differs(self.a, other.a) {
+            Some(TraceField::A)
+        } else if differs(self.x, other.x) {
+            Some(TraceField::X)
+        } else if differs(self.y, other.y) {
+            Some(TraceField::Y)
+        } else if differs(self.p, other.p) {
+            Some(TraceField::P)
+        } else if differs(self.sp, other.sp) {
+            Some(TraceField::Sp)
+        } else if differs(self.cycles, other.cycles) {
+            Some(TraceField::Cycles)
+        } else {
+            None
+        }
+    }
+
+    fn field(&self, field: TraceField) -> String {
+        let byte = |value: Option<u8>| value.map_or("-".to_string(), |value| format!("${:02X}", value));
+        match field {
+            TraceField::Pc => format!("${:04X}", self.pc),
+            TraceField::Opcode => format!("${:02X}", self.opcode),
+            TraceField::A => byte(self.a),
+            TraceField::X => byte(self.x),
+            TraceField::Y => byte(self.y),
+            TraceField::P => byte(self.p),
+            TraceField::Sp => byte(self.sp),
+            TraceField::Cycles => self.cycles.map_or("-".to_string(), |cycles| cycles.to_string()),
+            TraceField::Length => format!("line {}", self.number),
+        }
+    }
+}
+
+/// Parses every trace line of a file, skipping the lines that aren't one.
+pub fn parse_trace(text: &str) -> Vec<TraceLine> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| TraceLine::parse(i + 1, line))
+        .collect()
+}
+
+/// Compares two traces in the `Trace` display format, and finds the first line where they disagree
+/// on the PC, opcode, registers or cycles, with `context` lines around it. The traces are aligned
+/// on the first PC of the one that starts later, so a trace from $C000 can be compared with one
+/// that starts at reset.
+pub fn diff_traces(expected: &str, actual: &str, context: usize) -> Option<TraceDivergence> {
+    let (expected, actual) = (parse_trace(expected), parse_trace(actual));
+    let (expected_start, actual_start) = align(&expected, &actual);
+    let (expected, actual) = (&expected[expected_start..], &actual[actual_start..]);
+
+    let divergence = (0..expected.len().max(actual.len())).find_map(|i| {
+        match (expected.get(i), actual.get(i)) {
+            (Some(expected), Some(actual)) => expected.first_difference(actual),
+            _ => Some(TraceField::Length),
+        }.map(|field| (i, field))
+    });
+    divergence.map(|(i, field)| TraceDivergence {
+        field,
+        expected: expected.get(i).cloned(),
+        actual: actual.get(i).cloned(),
+        before: expected[i.saturating_sub(context)..i].to_vec(),
+        expected_after: expected.iter().skip(i + 1).take(context).cloned().collect(),
+        actual_after: actual.iter().skip(i + 1).take(context).cloned().collect(),
+    })
+}
+
+// Skips the start of whichever trace has lines before the other one's first PC.
+fn align(expected: &[TraceLine], actual: &[TraceLine]) -> (usize, usize) {
+    let find = |lines: &[TraceLine], first: Option<&TraceLine>| {
+        first.and_then(|first| lines.iter().position(|line| line.pc == first.pc))
+    };
+    match (find(expected, actual.first()), find(actual, expected.first())) {
+        (Some(expected_start), Some(actual_start)) if actual_start < expected_start => (0, actual_start),
+        (Some(expected_start), _) => (expected_start, 0),
+        (None, Some(actual_start)) => (0, actual_start),
+        (None, None) => (0, 0),
+    }
+}
+
+impl Display for TraceField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TraceField::Pc => "PC",
+            TraceField::Opcode => "opcode",
+            TraceField::A => "A",
+            TraceField::X => "X",
+            TraceField::Y => "Y",
+            TraceField::P => "P",
+            TraceField::Sp => "SP",
+            TraceField::Cycles => "cycle",
+            TraceField::Length => "length",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for TraceDivergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let line = |line: &Option<TraceLine>| line.as_ref().map_or("end of trace".to_string(), |line| line.field(self.field));
+        let number = |line: &Option<TraceLine>| line.as_ref().map_or("end".to_string(), |line| line.number.to_string());
+        if self.field == TraceField::Length {
+            writeln!(f, "Traces diverge: expected {} but got {}", line(&self.expected), line(&self.actual))?;
+        } else {
+            writeln!(
+                f,
+                "Traces diverge at expected line {}, actual line {}: {} is {} instead of {}",
+                number(&self.expected), number(&self.actual), self.field, line(&self.actual), line(&self.expected),
+            )?;
+        }
+        for line in &self.before {
+            writeln!(f, "  {:5} {}", line.number, line.text)?;
+        }
+        for line in self.expected.iter().chain(&self.expected_after) {
+            writeln!(f, "- {:5} {}", line.number, line.text)?;
+        }
+        for line in self.actual.iter().chain(&self.actual_after) {
+            writeln!(f, "+ {:5} {}", line.number, line.text)?;
+        }
+        Ok(())
+    }
+}
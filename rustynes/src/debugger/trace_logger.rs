@@ -0,0 +1,240 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use crate::cpu::disassembly::Trace;
+use crate::cpu::{Cpu, CpuStatus};
+use crate::debugger::Condition;
+use crate::memory::Bus;
+use crate::EmulationError;
+
+/// CPU cycles in an NTSC frame, rounded up. Without a PPU, frames are counted from cycles.
+pub const CYCLES_PER_FRAME: u64 = 29781;
+
+const TRACE_MAGIC: &[u8; 4] = b"6TRC";
+const TRACE_VERSION: u8 = 1;
+const TRACE_RECORD_LENGTH: usize = 19;
+
+/// How each instruction is written to the log.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// The `Trace` display format used by `logs/nestest.log`, without the PPU column.
+    Nestest,
+    /// Like Mesen's default trace log: `C000  JMP $C5F5  A:00 X:00 Y:00 S:FD P:nvUbdIzc Cycle:7`.
+    Mesen,
+    /// Like FCEUX's trace logger: `A:00 X:00 Y:00 S:FD P:nvUbdIzc  $C000:4C F5 C5  JMP $C5F5`.
+    Fceux,
+    /// Fixed size `TraceRecord`s after a short header, read back with `read_binary_trace`.
+    Binary,
+}
+
+/// Bounds the size of the log. Sizes are in bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceLimit {
+    Unlimited,
+    /// Stops logging once the file reaches the size.
+    Stop(u64),
+    /// Once the file reaches the size, it is moved to `<path>.1` and a new one is started, so the
+    /// last instructions logged are always kept.
+    Ring(u64),
+}
+
+/// Which instructions are logged. Instructions have to pass every filter that is set.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    pub pc_range: Option<RangeInclusive<u16>>,
+    pub frame_range: Option<RangeInclusive<u64>>,
+    /// Nothing is logged until the condition holds once.
+    pub trigger: Option<Condition>,
+}
+
+/// An instruction as stored in the binary format.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub address: u16,
+    pub length: u8,
+    pub bytes: [u8; 3],
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    pub register_sp: u8,
+    pub status_flags: CpuStatus,
+    pub cycles: u64,
+}
+
+/// Writes a trace of the CPU to a file as it runs, instead of keeping it in memory.
+pub struct TraceLogger {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    format: TraceFormat,
+    limit: TraceLimit,
+    pub filter: TraceFilter,
+    written: u64,
+    triggered: bool,
+}
+
+impl TraceLogger {
+    pub fn create(path: impl AsRef<Path>, format: TraceFormat, limit: TraceLimit) -> io::Result<TraceLogger> {
+        let path = path.as_ref().to_path_buf();
+        let mut logger = TraceLogger {
+            writer: BufWriter::new(File::create(&path)?),
+            path,
+            format,
+            limit,
+            filter: TraceFilter::default(),
+            written: 0,
+            triggered: false,
+        };
+        logger.write_header()?;
+        Ok(logger)
+    }
+
+    /// Logs the instruction the CPU is about to run, if it passes the filter.
+    pub fn log<B: Bus>(&mut self, cpu: &Cpu<B>) -> io::Result<()> {
+        if !self.passes(cpu) {
+            return Ok(());
+        }
+        match self.limit {
+            TraceLimit::Stop(size) if self.written >= size => return Ok(()),
+            TraceLimit::Ring(size) if self.written >= size => self.rotate()?,
+            _ => {}
+        }
+        let trace = cpu.trace();
+        let line = match self.format {
+            TraceFormat::Nestest => format!("{}\n", trace).into_bytes(),
+            TraceFormat::Mesen => format!(
+                "{:04X}  {:<32} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{} Cycle:{}\n",
+                trace.address, trace.instruction.to_string(), trace.register_a, trace.register_x,
+                trace.register_y, trace.register_sp, flags(trace.status_flags), trace.cycles,
+            ).into_bytes(),
+            TraceFormat::Fceux => format!(
+                "A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}  ${:04X}:{:<9} {}\n",
+                trace.register_a, trace.register_x, trace.register_y, trace.register_sp,
+                flags(trace.status_flags), trace.address, instruction_bytes(&trace), trace.instruction,
+            ).into_bytes(),
+            TraceFormat::Binary => TraceRecord::from_trace(&trace).to_bytes().to_vec(),
+        };
+        self.writer.write_all(&line)?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn passes<B: Bus>(&mut self, cpu: &Cpu<B>) -> bool {
+        if !self.triggered {
+            self.triggered = self.filter.trigger.as_ref().is_none_or(|trigger| trigger.holds(cpu));
+        }
+        self.triggered
+            && self.filter.pc_range.as_ref().is_none_or(|range| range.contains(&cpu.register_pc))
+            && self.filter.frame_range.as_ref().is_none_or(|range| range.contains(&(cpu.cycles / CYCLES_PER_FRAME)))
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let mut previous = self.path.clone().into_os_string();
+        previous.push(".1");
+        fs::rename(&self.path, previous)?;
+        self.writer = BufWriter::new(File::create(&self.path)?);
+        self.written = 0;
+        self.write_header()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.format == TraceFormat::Binary {
+            self.writer.write_all(TRACE_MAGIC)?;
+            self.writer.write_all(&[TRACE_VERSION])?;
+            self.written += TRACE_MAGIC.len() as u64 + 1;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TraceLogger {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+impl TraceRecord {
+    pub fn from_trace(trace: &Trace) -> TraceRecord {
+        let mut bytes = [trace.instruction.opcode, 0, 0];
+        let operands = trace.instruction.operands.iter().take(trace.instruction.length as usize - 1);
+        for (byte, operand) in bytes[1..].iter_mut().zip(operands) {
+            *byte = *operand;
+        }
+        TraceRecord {
+            address: trace.address,
+            length: trace.instruction.length as u8,
+            bytes,
+            register_a: trace.register_a,
+            register_x: trace.register_x,
+            register_y: trace.register_y,
+            register_sp: trace.register_sp,
+            status_flags: trace.status_flags,
+            cycles: trace.cycles,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; TRACE_RECORD_LENGTH] {
+        let mut bytes = [0; TRACE_RECORD_LENGTH];
+        bytes[0..2].copy_from_slice(&self.address.to_le_bytes());
+        bytes[2] = self.length;
+        bytes[3..6].copy_from_slice(&self.bytes);
+        bytes[6..11].copy_from_slice(&[
+            self.register_a,
+            self.register_x,
+            self.register_y,
+            self.register_sp,
+            self.status_flags.status,
+        ]);
+        bytes[11..19].copy_from_slice(&self.cycles.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> TraceRecord {
+        TraceRecord {
+            address: u16::from_le_bytes([bytes[0], bytes[1]]),
+            length: bytes[2],
+            bytes: [bytes[3], bytes[4], bytes[5]],
+            register_a: bytes[6],
+            register_x: bytes[7],
+            register_y: bytes[8],
+            register_sp: bytes[9],
+            status_flags: CpuStatus { status: bytes[10] },
+            cycles: u64::from_le_bytes(bytes[11..19].try_into().unwrap()),
+        }
+    }
+}
+
+/// Reads a log written in `TraceFormat::Binary`.
+pub fn read_binary_trace(bytes: &[u8]) -> Result<Vec<TraceRecord>, EmulationError> {
+    let header = TRACE_MAGIC.len() + 1;
+    if bytes.len() < header
+        || &bytes[0..4] != TRACE_MAGIC
+        || bytes[4] != TRACE_VERSION
+        || !(bytes.len() - header).is_multiple_of(TRACE_RECORD_LENGTH)
+    {
+        return Err(EmulationError::InvalidTraceLog);
+    }
+    Ok(bytes[header..].chunks(TRACE_RECORD_LENGTH).map(TraceRecord::from_bytes).collect())
+}
+
+// The flags as `NV-BDIZC` letters, uppercase when set.
+fn flags(status_flags: CpuStatus) -> String {
+    "nvubdizc".chars()
+        .enumerate()
+        .map(|(i, flag)| if status_flags.status & (0x80 >> i) != 0 { flag.to_ascii_uppercase() } else { flag })
+        .collect()
+}
+
+fn instruction_bytes(trace: &Trace) -> String {
+    std::iter::once(&trace.instruction.opcode)
+        .chain(&trace.instruction.operands)
+        .take(trace.instruction.length as usize)
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1,4 +1,5 @@
 pub mod cpu;
+pub mod debugger;
 pub mod memory;
 pub mod ui;
 pub mod rom;
@@ -21,6 +22,21 @@ pub enum EmulationError {
     UnsuportedAddressingMode,
     #[error("Cannot step while halted")]
     Halted,
+    #[error("Invalid CPU state")]
+    InvalidState,
+    #[error("Unsupported CPU state version: {0}")]
+    UnsupportedStateVersion(u8),
+    #[error("Invalid condition at column {0}: {1}")]
+    InvalidCondition(usize, String),
+    #[error("Code/data log does not match the ROM size")]
+    InvalidCodeDataLog,
+    #[error("Invalid binary trace log")]
+    InvalidTraceLog,
+    #[error("Mapping ${0:04X}-${1:04X} does not start and end on page boundaries")]
+    InvalidMapping(u16, u16),
+    // Stops an instruction run by `Cpu::tick` at the access after the one of the current cycle
+    #[error("Instruction stopped at the end of the cycle")]
+    CycleEnded,
 
     #[error("Invalid address")]
     InvalidAddress(u16),
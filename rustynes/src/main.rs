@@ -1,7 +1,17 @@
+use std::{env, fs, process};
 use egui::Vec2;
+use rustynes::debugger::diff_traces;
 use rustynes::ui::RustyNesUi;
 
+const DIFF_CONTEXT_LINES: usize = 5;
+
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("diff-trace") {
+        diff_trace(&args[2..]);
+        return;
+    }
+
     let options = eframe::NativeOptions {
         initial_window_size: Some(Vec2 {
             x: 1300.0,
@@ -16,3 +26,22 @@ fn main() {
         Box::new(|cc| Box::new(RustyNesUi::new(cc))),
     );
 }
+
+// `rustynes diff-trace <expected> <actual>` exits with 1 when the traces diverge.
+fn diff_trace(paths: &[String]) {
+    let [expected, actual] = paths else {
+        eprintln!("Usage: rustynes diff-trace <expected log> <actual log>");
+        process::exit(2);
+    };
+    let read = |path: &String| fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("Can't read {}: {}", path, e);
+        process::exit(2);
+    });
+    match diff_traces(&read(expected), &read(actual), DIFF_CONTEXT_LINES) {
+        Some(divergence) => {
+            print!("{}", divergence);
+            process::exit(1);
+        }
+        None => println!("Traces match"),
+    }
+}
@@ -0,0 +1,47 @@
+use crate::memory::Bus;
+use crate::EmulationError;
+
+/// 64KB of plain RAM, with nothing mapped over it, for running CPU test programs and vectors.
+pub struct FlatBus {
+    memory: Box<[u8; 0x10000]>,
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
+        self.peek(address)
+    }
+
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        Ok(self.memory[address as usize])
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
+        self.memory[address as usize] = value;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.memory.fill(0);
+    }
+}
+
+impl FlatBus {
+    pub fn new() -> FlatBus {
+        FlatBus {
+            memory: Box::new([0; 0x10000]),
+        }
+    }
+
+    /// Copies `bytes` into memory starting at `address`, wrapping around at the end.
+    pub fn load(&mut self, address: u16, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.memory[address.wrapping_add(i as u16) as usize] = *byte;
+        }
+    }
+}
+
+impl Default for FlatBus {
+    fn default() -> FlatBus {
+        FlatBus::new()
+    }
+}
@@ -0,0 +1,200 @@
+use std::ops::RangeInclusive;
+use crate::memory::Bus;
+use crate::EmulationError;
+
+/// Mappings are made of 32 byte pages, the smallest block the NES maps anything to.
+pub const PAGE_SIZE: u16 = 1 << PAGE_BITS;
+const PAGE_BITS: u16 = 5;
+
+/// Something that answers on the bus, such as RAM, a ROM or a chip's registers. It sees addresses
+/// relative to the start of its mapping, with the mapping's mirroring mask applied.
+pub trait Device {
+    /// Reads as the CPU does, with any side effects the read has on the device.
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
+        self.peek(address)
+    }
+    fn peek(&self, address: u16) -> Result<u8, EmulationError>;
+    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError>;
+    fn reset(&mut self) {}
+
+    /// Where `address` maps to in the cartridge's PRG ROM, if the device is one.
+    fn prg_rom_offset(&self, _address: u16) -> Option<usize> {
+        None
+    }
+}
+
+struct Mapping {
+    start: u16,
+    mask: u16,
+    device: Box<dyn Device + Send + Sync>,
+}
+
+/// A bus put together from devices mapped on address ranges, so machines can share their parts.
+/// Unmapped reads return the open bus value, the last value on the data bus.
+pub struct MappedBus {
+    // Slots are freed once no page belongs to them, and reused by later mappings
+    mappings: Vec<Option<Mapping>>,
+    // The mapping each page belongs to
+    pages: Vec<Option<usize>>,
+    /// Fails unmapped reads with `InvalidRead`, instead of returning the open bus value.
+    pub strict_reads: bool,
+    open_bus: u8,
+}
+
+impl MappedBus {
+    pub fn new() -> MappedBus {
+        MappedBus {
+            mappings: Vec::new(),
+            pages: vec![None; 0x10000 >> PAGE_BITS],
+            strict_reads: false,
+            open_bus: 0,
+        }
+    }
+
+    /// Maps `device` on `range`, which has to start and end on page boundaries. The device sees
+    /// `(address - start) & mask`, so a mask smaller than the range mirrors it. Replaces whatever
+    /// was mapped on the range before, and drops devices that are left with no pages.
+    pub fn map(
+        &mut self,
+        range: RangeInclusive<u16>,
+        mask: u16,
+        device: impl Device + Send + Sync + 'static,
+    ) -> Result<(), EmulationError> {
+        let (start, end) = (*range.start(), *range.end());
+        if start > end || start % PAGE_SIZE != 0 || end % PAGE_SIZE != PAGE_SIZE - 1 {
+            return Err(EmulationError::InvalidMapping(start, end));
+        }
+        let pages = (start >> PAGE_BITS) as usize..=(end >> PAGE_BITS) as usize;
+        self.pages[pages.clone()].fill(None);
+        for (index, mapping) in self.mappings.iter_mut().enumerate() {
+            if mapping.is_some() && !self.pages.contains(&Some(index)) {
+                *mapping = None;
+            }
+        }
+
+        let mapping = Mapping {
+            start,
+            mask,
+            device: Box::new(device),
+        };
+        let index = match self.mappings.iter().position(Option::is_none) {
+            Some(index) => {
+                self.mappings[index] = Some(mapping);
+                index
+            }
+            None => {
+                self.mappings.push(Some(mapping));
+                self.mappings.len() - 1
+            }
+        };
+        self.pages[pages].fill(Some(index));
+        Ok(())
+    }
+
+    /// How many devices are mapped.
+    pub fn device_count(&self) -> usize {
+        self.mappings.iter().flatten().count()
+    }
+
+    /// The last value on the data bus.
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus
+    }
+
+    // The mapping of `address` and the address its device sees
+    fn lookup(&self, address: u16) -> Option<(usize, u16)> {
+        self.pages[(address >> PAGE_BITS) as usize].map(|index| {
+            let mapping = self.mapping(index);
+            (index, (address - mapping.start) & mapping.mask)
+        })
+    }
+
+    fn mapping(&self, index: usize) -> &Mapping {
+        self.mappings[index].as_ref().unwrap()
+    }
+
+    fn mapping_mut(&mut self, index: usize) -> &mut Mapping {
+        self.mappings[index].as_mut().unwrap()
+    }
+
+    fn unmapped_read(&self) -> Result<u8, EmulationError> {
+        if self.strict_reads {
+            Err(EmulationError::InvalidRead)
+        } else {
+            Ok(self.open_bus)
+        }
+    }
+}
+
+impl Bus for MappedBus {
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
+        let value = match self.lookup(address) {
+            Some((index, address)) => self.mapping_mut(index).device.read(address)?,
+            None => self.unmapped_read()?,
+        };
+        self.open_bus = value;
+        Ok(value)
+    }
+
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        match self.lookup(address) {
+            Some((index, address)) => self.mapping(index).device.peek(address),
+            None => self.unmapped_read(),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
+        self.open_bus = value;
+        match self.lookup(address) {
+            Some((index, address)) => self.mapping_mut(index).device.write(address, value),
+            None => Err(EmulationError::InvalidWrite),
+        }
+    }
+
+    fn reset(&mut self) {
+        for mapping in self.mappings.iter_mut().flatten() {
+            mapping.device.reset();
+        }
+        self.open_bus = 0;
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.lookup(address)
+            .and_then(|(index, address)| self.mapping(index).device.prg_rom_offset(address))
+    }
+}
+
+impl Default for MappedBus {
+    fn default() -> MappedBus {
+        MappedBus::new()
+    }
+}
+
+/// RAM that is cleared on reset.
+pub struct Ram {
+    memory: Vec<u8>,
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Ram {
+        Ram {
+            memory: vec![0; size],
+        }
+    }
+}
+
+impl Device for Ram {
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        self.memory.get(address as usize).copied().ok_or(EmulationError::InvalidRead)
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
+        let byte = self.memory.get_mut(address as usize).ok_or(EmulationError::InvalidWrite)?;
+        *byte = value;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.memory.fill(0);
+    }
+}
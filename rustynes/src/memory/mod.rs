@@ -1,24 +1,77 @@
 use crate::EmulationError;
 
+pub mod flat;
+pub mod mapped;
 pub mod nes;
 #[cfg(test)]
 mod test;
 pub mod test_game;
 
 pub trait Bus {
-    fn read(&self, address: u16) -> Result<u8, EmulationError>;
+    /// Reads as the CPU does, with any side effects the read has on the hardware.
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError>;
+    /// Reads without side effects, for debuggers and traces that look at memory without the
+    /// program knowing.
+    fn peek(&self, address: u16) -> Result<u8, EmulationError>;
     fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError>;
-    fn read_word(&self, address: u16) -> Result<u16, EmulationError> {
+    fn read_word(&mut self, address: u16) -> Result<u16, EmulationError> {
         Ok(u16::from_le_bytes([
             self.read(address)?,
-            self.read(address + 1)?,
+            self.read(address.wrapping_add(1))?,
+        ]))
+    }
+
+    fn peek_word(&self, address: u16) -> Result<u16, EmulationError> {
+        Ok(u16::from_le_bytes([
+            self.peek(address)?,
+            self.peek(address.wrapping_add(1))?,
         ]))
     }
 
     fn write_word(&mut self, address: u16, value: u16) -> Result<(), EmulationError> {
         self.write(address, value as u8)?;
-        self.write(address + 1, (value >> 8) as u8)?;
+        self.write(address.wrapping_add(1), (value >> 8) as u8)?;
         Ok(())
     }
     fn reset(&mut self);
+
+    /// Where `address` maps to in the cartridge's PRG ROM, if it does, for tools that track
+    /// which ROM bytes are used.
+    fn prg_rom_offset(&self, _address: u16) -> Option<usize> {
+        None
+    }
+}
+
+impl<B: Bus + ?Sized> Bus for Box<B> {
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
+        (**self).read(address)
+    }
+
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        (**self).peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
+        (**self).write(address, value)
+    }
+
+    fn read_word(&mut self, address: u16) -> Result<u16, EmulationError> {
+        (**self).read_word(address)
+    }
+
+    fn peek_word(&self, address: u16) -> Result<u16, EmulationError> {
+        (**self).peek_word(address)
+    }
+
+    fn write_word(&mut self, address: u16, value: u16) -> Result<(), EmulationError> {
+        (**self).write_word(address, value)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        (**self).prg_rom_offset(address)
+    }
 }
@@ -1,67 +1,65 @@
+use std::ops::{Deref, DerefMut};
 use crate::memory::Bus;
+use crate::memory::mapped::{MappedBus, Ram};
 use crate::EmulationError;
 use crate::rom::Rom;
 
 
 const RAM_START: u16 = 0x0000;
 const RAM_END: u16 = 0x1FFF;
-const PPU_REGISTERS_START: u16 = 0x2000;
-const PPU_REGISTERS_END: u16 = 0x3FFF;
+const RAM_MIRROR_MASK: u16 = 0x07FF;
 const ROM_START: u16 = 0x8000;
 const ROM_END: u16 = 0xFFFF;
+const ROM_MASK: u16 = 0x7FFF;
 
+/// The NES memory map: 2KB of RAM mirrored up to $1FFF and the cartridge's PRG ROM from $8000.
+/// Everything else is unmapped for now. Derefs to its `MappedBus`, which has the open bus value
+/// and `strict_reads`.
 pub struct NesBus {
-    ram: [u8; 0x2000],
-    rom: Rom,
+    bus: MappedBus,
+}
+
+impl NesBus {
+    pub fn new(rom: Rom) -> NesBus {
+        let mut bus = MappedBus::new();
+        bus.map(RAM_START..=RAM_END, RAM_MIRROR_MASK, Ram::new(0x0800)).unwrap();
+        bus.map(ROM_START..=ROM_END, ROM_MASK, rom).unwrap();
+        NesBus { bus }
+    }
+}
+
+impl Deref for NesBus {
+    type Target = MappedBus;
+
+    fn deref(&self) -> &MappedBus {
+        &self.bus
+    }
+}
+
+impl DerefMut for NesBus {
+    fn deref_mut(&mut self) -> &mut MappedBus {
+        &mut self.bus
+    }
 }
 
 impl Bus for NesBus {
-    fn read(&self, address: u16) -> Result<u8, EmulationError> {
-        match address {
-            RAM_START..=RAM_END => {
-                let mirror = (address - RAM_START) & 0b0000_0111_1111_1111;
-                Ok(self.ram[mirror as usize])
-            },
-            PPU_REGISTERS_START..=PPU_REGISTERS_END => {
-                let _mirror = address & 0b0010_0000_0000_0111;
-                Err(EmulationError::InvalidRead)
-            },
-            ROM_START..=ROM_END => {
-                Ok(self.rom.read_prg_rom(address - ROM_START))
-            },
-            _ => Err(EmulationError::InvalidRead),
-        }
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
+        self.bus.read(address)
     }
 
-    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
-        match address {
-            RAM_START..=RAM_END => {
-                let mirror = (address - RAM_START) & 0b00000111_11111111;
-                self.ram[mirror as usize] = value;
-                Ok(())
-            },
-            PPU_REGISTERS_START..=PPU_REGISTERS_END => {
-                let _mirror = address & 0b00100000_00000111;
-                Err(EmulationError::InvalidWrite)
-            },
-            ROM_START..=ROM_END => {
-                Err(EmulationError::InvalidWrite)
-            },
-            _ => Err(EmulationError::InvalidWrite),
-        }
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        self.bus.peek(address)
     }
 
+    fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
+        self.bus.write(address, value)
+    }
 
     fn reset(&mut self) {
-        self.ram = [0; 0x2000];
+        self.bus.reset()
     }
-}
 
-impl NesBus {
-    pub fn new(rom: Rom) -> NesBus {
-        NesBus {
-            ram: [0; 0x2000],
-            rom,
-        }
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        self.bus.prg_rom_offset(address)
     }
 }
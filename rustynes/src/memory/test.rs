@@ -1,31 +1,148 @@
 use crate::memory::nes::NesBus;
 use crate::memory::Bus;
-use egui::Memory;
+use crate::memory::flat::FlatBus;
+use crate::memory::mapped::{Device, MappedBus, Ram};
+use crate::rom::Rom;
+use crate::EmulationError;
+
+fn rom_with_prg(prg: &[u8]) -> Rom {
+    let mut raw = vec![0; 16 + 0x4000];
+    raw[0..4].copy_from_slice(b"NES\x1A");
+    raw[4] = 1;
+    raw[16..16 + prg.len()].copy_from_slice(prg);
+    Rom::new(&raw).unwrap()
+}
+
+fn nes_bus_with_prg(prg: &[u8]) -> NesBus {
+    NesBus::new(rom_with_prg(prg))
+}
 
-/*
 #[test]
 fn test_load_rom() {
-    let mut memory = NesBus::new();
-    memory.load_rom(&[0x00, 0x01, 0x02, 0x03]).unwrap();
+    let mut memory = nes_bus_with_prg(&[0x00, 0x01, 0x02, 0x03]);
     assert_eq!(memory.read(0x8000).unwrap(), 0x00);
     assert_eq!(memory.read(0x8001).unwrap(), 0x01);
     assert_eq!(memory.read(0x8002).unwrap(), 0x02);
     assert_eq!(memory.read(0x8003).unwrap(), 0x03);
+    // 16KB PRG ROMs are mirrored into $C000-$FFFF
+    assert_eq!(memory.read(0xC003).unwrap(), 0x03);
 }
 
 #[test]
 fn test_write_read() {
-    let mut memory = NesBus::new();
+    let mut memory = nes_bus_with_prg(&[]);
     memory.write(0x1234, 0xab).unwrap();
     assert_eq!(memory.read(0x1234).unwrap(), 0xab);
+    // RAM is mirrored every 2KB
+    assert_eq!(memory.read(0x0234).unwrap(), 0xab);
 }
 
 #[test]
 fn test_write_word() {
-    let mut memory = NesBus::new();
+    let mut memory = nes_bus_with_prg(&[]);
     memory.write_word(0x1234, 0xabcd).unwrap();
     assert_eq!(memory.read(0x1234).unwrap(), 0xcd);
     assert_eq!(memory.read(0x1235).unwrap(), 0xab);
     assert_eq!(memory.read_word(0x1234).unwrap(), 0xabcd);
+    assert_eq!(memory.peek_word(0x1234).unwrap(), 0xabcd);
+}
+
+#[test]
+fn test_word_wraps_around() {
+    let mut memory = FlatBus::new();
+    memory.write_word(0xFFFF, 0xabcd).unwrap();
+    assert_eq!(memory.peek(0xFFFF).unwrap(), 0xcd);
+    assert_eq!(memory.peek(0x0000).unwrap(), 0xab);
+    assert_eq!(memory.peek_word(0xFFFF).unwrap(), 0xabcd);
+    assert_eq!(memory.read_word(0xFFFF).unwrap(), 0xabcd);
+}
+
+#[test]
+fn test_peek() {
+    let mut memory = nes_bus_with_prg(&[0x42]);
+    memory.write(0x0010, 0xab).unwrap();
+    assert_eq!(memory.peek(0x0010).unwrap(), 0xab);
+    assert_eq!(memory.peek(0x8000).unwrap(), 0x42);
+    // Peeking doesn't drive the data bus
+    assert_eq!(memory.open_bus(), 0xab);
+}
+
+#[test]
+fn test_open_bus() {
+    let mut memory = nes_bus_with_prg(&[0x42]);
+    assert_eq!(memory.read(0x8000).unwrap(), 0x42);
+    assert_eq!(memory.read(0x5000).unwrap(), 0x42);
+    assert_eq!(memory.peek(0x2002).unwrap(), 0x42);
+    memory.write(0x0010, 0xab).unwrap();
+    assert_eq!(memory.read(0x4018).unwrap(), 0xab);
+
+    memory.strict_reads = true;
+    assert!(matches!(memory.read(0x5000), Err(EmulationError::InvalidRead)));
+    assert!(matches!(memory.peek(0x2002), Err(EmulationError::InvalidRead)));
+}
+
+// Counts its reads, like a register that changes when the CPU reads it.
+struct ReadCounter {
+    reads: u8,
+}
+
+impl Device for ReadCounter {
+    fn read(&mut self, _address: u16) -> Result<u8, EmulationError> {
+        self.reads += 1;
+        Ok(self.reads)
+    }
+
+    fn peek(&self, _address: u16) -> Result<u8, EmulationError> {
+        Ok(self.reads)
+    }
+
+    fn write(&mut self, _address: u16, _value: u8) -> Result<(), EmulationError> {
+        Err(EmulationError::InvalidWrite)
+    }
+}
+
+#[test]
+fn test_nes_bus_mapping() {
+    let mut memory = nes_bus_with_prg(&[0x42, 0x43]);
+    memory.write(0x0010, 0xab).unwrap();
+    // RAM is mirrored every 2KB, and 16KB PRG ROMs into $C000-$FFFF
+    assert_eq!(memory.read(0x1810).unwrap(), 0xab);
+    assert_eq!(memory.read(0xC001).unwrap(), 0x43);
+    assert_eq!(memory.prg_rom_offset(0xC001), Some(1));
+    assert_eq!(memory.prg_rom_offset(0x0010), None);
+    assert_eq!(memory.read(0x5000).unwrap(), 0x43);
+    assert!(matches!(memory.write(0x5000, 0x00), Err(EmulationError::InvalidWrite)));
+    memory.strict_reads = true;
+    assert!(matches!(memory.read(0x5000), Err(EmulationError::InvalidRead)));
+
+    memory.reset();
+    assert_eq!(memory.peek(0x0010).unwrap(), 0x00);
+}
+
+#[test]
+fn test_mapped_devices() {
+    let mut memory = MappedBus::new();
+    let misaligned = memory.map(0x6010..=0x601F, 0xFFFF, Ram::new(0x10));
+    assert!(matches!(misaligned, Err(EmulationError::InvalidMapping(0x6010, 0x601F))));
+    memory.map(0x6000..=0x7FFF, 0x1FFF, Ram::new(0x2000)).unwrap();
+    // Replaces the first page of the RAM
+    memory.map(0x6000..=0x601F, 0x0000, ReadCounter { reads: 0 }).unwrap();
+    memory.write(0x6020, 0xcd).unwrap();
+    assert_eq!(memory.read(0x6020).unwrap(), 0xcd);
+
+    assert_eq!(memory.read(0x6000).unwrap(), 1);
+    assert_eq!(memory.read(0x601F).unwrap(), 2);
+    // Peeking doesn't count as a read
+    assert_eq!(memory.peek(0x6000).unwrap(), 2);
+    assert_eq!(memory.read(0x6005).unwrap(), 3);
+    assert_eq!(memory.device_count(), 2);
+
+    // Devices left without pages are dropped, and their slots reused
+    for _ in 0..4 {
+        memory.map(0x6020..=0x7FFF, 0x1FFF, Ram::new(0x2000)).unwrap();
+    }
+    assert_eq!(memory.device_count(), 2);
+    memory.map(0x6000..=0x7FFF, 0x1FFF, Ram::new(0x2000)).unwrap();
+    assert_eq!(memory.device_count(), 1);
+    assert_eq!(memory.peek(0x6000).unwrap(), 0x00);
 }
-*/
\ No newline at end of file
@@ -6,14 +6,18 @@ pub struct TestGameBus {
 }
 
 impl Bus for TestGameBus {
-    fn read(&self, address: u16) -> Result<u8, EmulationError> {
+    fn read(&mut self, address: u16) -> Result<u8, EmulationError> {
         if address == 0xFE {
             Ok(rand::random())
         } else {
-            Ok(self.memory[address as usize])
+            self.peek(address)
         }
     }
 
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        Ok(self.memory[address as usize])
+    }
+
     fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
         self.memory[address as usize] = value;
         Ok(())
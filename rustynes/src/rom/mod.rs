@@ -1,4 +1,6 @@
 use thiserror::Error;
+use crate::memory::mapped::Device;
+use crate::EmulationError;
 
 const NES_MAGIC: &[u8; 4] = b"NES\x1A";
 const PRG_ROM_PAGE_SIZE: usize = 16384;
@@ -22,7 +24,7 @@ pub enum Mirroring {
 
 pub struct Rom {
     prg_rom: [u8; 0x8000],
-    _chr_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
     _mapper: u8,
     _mirroring: Mirroring,
     mirror_prg_rom: bool,
@@ -70,7 +72,7 @@ impl Rom {
 
         Ok(Rom{
             prg_rom,
-            _chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
             _mapper: mapper,
             _mirroring: mirroring,
             mirror_prg_rom,
@@ -78,10 +80,41 @@ impl Rom {
     }
 
     pub fn read_prg_rom(&self, address: u16) -> u8 {
+        self.prg_rom[self.prg_rom_index(address)]
+    }
+
+    /// Where an address in the $8000-$FFFF PRG ROM window maps to in the PRG ROM.
+    pub fn prg_rom_index(&self, address: u16) -> usize {
+        if self.mirror_prg_rom {
+            address as usize % 0x4000
+        } else {
+            address as usize
+        }
+    }
+
+    pub fn prg_rom_size(&self) -> usize {
         if self.mirror_prg_rom {
-            self.prg_rom[address as usize % 0x4000]
+            0x4000
         } else {
-            self.prg_rom[address as usize]
+            0x8000
         }
     }
+
+    pub fn chr_rom_size(&self) -> usize {
+        self.chr_rom.len()
+    }
+}
+
+impl Device for Rom {
+    fn peek(&self, address: u16) -> Result<u8, EmulationError> {
+        Ok(self.read_prg_rom(address))
+    }
+
+    fn write(&mut self, _address: u16, _value: u8) -> Result<(), EmulationError> {
+        Err(EmulationError::InvalidWrite)
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        Some(self.prg_rom_index(address))
+    }
 }
\ No newline at end of file
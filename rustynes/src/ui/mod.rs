@@ -1,20 +1,55 @@
 use std::sync::{Arc, mpsc};
 use std::sync::mpsc::{Receiver, Sender};
 use std::{fs, thread};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use crate::cpu::Cpu;
+use crate::cpu::{BoxedCpu, HaltPolicy};
 use eframe::epaint::Rounding;
 use eframe::{egui, CreationContext, Frame};
 use eframe::epaint::mutex::RwLock;
 use egui::{Color32, Context, Key, Rect, Sense, Vec2};
 use crate::cpu::disassembly::Instruction;
+use crate::debugger::{Breakpoint, CodeDataLogger, Condition, Debugger, RunTarget, StopReason, TraceFilter, TraceFormat, TraceLimit, TraceLogger, Watchpoint, CDL_CODE, CDL_DATA};
 use crate::memory::nes::NesBus;
 use crate::rom::Rom;
 
+const ROM_PATH: &str = "roms/nestest.nes";
+const TRACE_LOG_PATH: &str = "log";
+const PROFILE_PATH: &str = "profile.folded";
+
 pub struct RustyNesUi {
-    cpu: Arc<RwLock<Cpu>>,
+    cpu: Arc<RwLock<BoxedCpu>>,
     stop_tx: Option<Sender<()>>,
-    halted_rx: Option<Receiver<()>>,
+    halted_rx: Option<Receiver<Option<StopReason>>>,
+    debugger: Arc<RwLock<Debugger>>,
+    stop_reason: Option<StopReason>,
+    // Next to the ROM with the extension swapped, where FCEUX keeps it
+    code_data_log_path: PathBuf,
+    code_data_log_error: Option<String>,
+    run_to_address: String,
+    last_write_address: String,
+    profile_functions: bool,
+    profile_sort_column: usize,
+    profile_error: Option<String>,
+    trace_format: TraceFormat,
+    trace_pc_range: bool,
+    trace_start_address: String,
+    trace_end_address: String,
+    trace_frame_range: bool,
+    trace_start_frame: String,
+    trace_end_frame: String,
+    trace_trigger: String,
+    trace_ring: bool,
+    trace_ring_size: String,
+    trace_error: Option<String>,
+    breakpoint_address: String,
+    watchpoint_start_address: String,
+    watchpoint_end_address: String,
+    watchpoint_read: bool,
+    watchpoint_write: bool,
+    watchpoint_execute: bool,
+    condition: String,
+    condition_error: Option<String>,
     memory_start_address: String,
     memory_end_address: String,
     memory_write_address: String,
@@ -26,17 +61,53 @@ impl RustyNesUi {
     pub fn new(cc: &CreationContext) -> Self {
         cc.egui_ctx.set_visuals(egui::Visuals::dark());
 
-        let mut rom_bytes = fs::read("roms/nestest.nes").unwrap();
+        let mut rom_bytes = fs::read(ROM_PATH).unwrap();
         rom_bytes[0x400c] = 0x00;
         let rom = Rom::new(&rom_bytes).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.code_data_logger = Some(CodeDataLogger::new(rom.prg_rom_size(), rom.chr_rom_size()));
         let bus = NesBus::new(rom);
 
-        let mut cpu = Cpu::new(Box::new(bus));
+        let mut cpu = BoxedCpu::new(Box::new(bus));
+        // nestest returns into zeroed memory once it is done
+        cpu.halt_policy = HaltPolicy {
+            on_brk: true,
+            on_jam: true,
+            ..HaltPolicy::default()
+        };
         cpu.reset();
         RustyNesUi {
             cpu: Arc::new(RwLock::new(cpu)),
             stop_tx: None,
             halted_rx: None,
+            debugger: Arc::new(RwLock::new(debugger)),
+            code_data_log_path: Path::new(ROM_PATH).with_extension("cdl"),
+            code_data_log_error: None,
+            stop_reason: None,
+            run_to_address: "0000".to_string(),
+            last_write_address: "0000".to_string(),
+            profile_functions: false,
+            profile_sort_column: 2,
+            profile_error: None,
+            trace_format: TraceFormat::Nestest,
+            trace_pc_range: false,
+            trace_start_address: "0000".to_string(),
+            trace_end_address: "FFFF".to_string(),
+            trace_frame_range: false,
+            trace_start_frame: "0".to_string(),
+            trace_end_frame: "60".to_string(),
+            trace_trigger: String::new(),
+            trace_ring: false,
+            trace_ring_size: "65536".to_string(),
+            trace_error: None,
+            breakpoint_address: "0000".to_string(),
+            watchpoint_start_address: "0000".to_string(),
+            watchpoint_end_address: "0000".to_string(),
+            watchpoint_read: true,
+            watchpoint_write: true,
+            watchpoint_execute: false,
+            condition: String::new(),
+            condition_error: None,
             memory_start_address: "0000".to_string(),
             memory_end_address: "0100".to_string(),
             memory_write_address: "0000".to_string(),
@@ -61,6 +132,11 @@ impl eframe::App for RustyNesUi {
         self.draw_disassembly_window(ctx);
         self.draw_stack_window(ctx);
         self.draw_memory_write_window(ctx);
+        self.draw_breakpoints_window(ctx);
+        self.draw_call_stack_window(ctx);
+        self.draw_profiler_window(ctx);
+        self.draw_code_data_logger_window(ctx);
+        self.draw_trace_logger_window(ctx);
         //self.draw_display_window(ctx);
         //self.handle_input(ctx);
 
@@ -163,7 +239,8 @@ impl RustyNesUi {
             .resizable(false)
             .show(ctx, |ui| {
                 if self.stop_tx.is_some() {
-                    if self.halted_rx.as_ref().unwrap().try_recv().is_ok() {
+                    if let Ok(stop_reason) = self.halted_rx.as_ref().unwrap().try_recv() {
+                        self.stop_reason = stop_reason;
                         self.stop_tx = None;
                         self.halted_rx = None;
                     } else if ui.button("Stop").clicked() {
@@ -173,17 +250,60 @@ impl RustyNesUi {
                     }
                 } else {
                     if ui.button("Run").clicked() {
-                        self.create_run_thread(false);
+                        self.run_until(None, false);
                     }
                     if ui.button("Run and save trace").clicked() {
-                        self.create_run_thread(true);
+                        self.run_until(None, true);
                     }
                     if ui.button("Step").clicked() {
-                        self.cpu.write().step();
+                        self.stop_reason = None;
+                        let mut debugger = self.debugger.write();
+                        debugger.target = None;
+                        let _ = debugger.step(&mut *self.cpu.write());
+                    }
+                    if ui.button("Step Back").clicked() {
+                        self.stop_reason = None;
+                        self.debugger.write().history.step_back(&mut *self.cpu.write());
+                    }
+                    if ui.button("Step Over").clicked() {
+                        let target = RunTarget::step_over(&*self.cpu.read());
+                        self.run_until(Some(target), false);
+                    }
+                    if ui.button("Step Out").clicked() {
+                        let target = RunTarget::step_out(&*self.cpu.read());
+                        self.run_until(Some(target), false);
+                    }
+                    let old_run_to_address = self.run_to_address.clone();
+                    let run_to = ui.horizontal(|ui| {
+                        let clicked = ui.button("Run To").clicked();
+                        ui.text_edit_singleline(&mut self.run_to_address);
+                        clicked
+                    }).inner;
+                    let address = validate_word(&mut self.run_to_address, old_run_to_address);
+                    if run_to {
+                        self.run_until(Some(RunTarget::RunTo(address)), false);
                     }
                     if ui.button("Reset").clicked() {
+                        self.stop_reason = None;
+                        self.debugger.write().history.clear();
+                        self.debugger.write().call_stack.clear();
                         self.cpu.write().reset();
                     }
+                    let old_last_write_address = self.last_write_address.clone();
+                    let back_to_write = ui.horizontal(|ui| {
+                        let clicked = ui.button("Back To Last Write").clicked();
+                        ui.text_edit_singleline(&mut self.last_write_address);
+                        clicked
+                    }).inner;
+                    let address = validate_word(&mut self.last_write_address, old_last_write_address);
+                    if back_to_write {
+                        self.stop_reason = None;
+                        self.debugger.write().history.back_to_last_write(&mut *self.cpu.write(), address);
+                    }
+                    ui.label(format!("History: {} instructions", self.debugger.read().history.len()));
+                }
+                if let Some(stop_reason) = &self.stop_reason {
+                    ui.label(stop_reason.to_string());
                 }
             });
     }
@@ -222,7 +342,7 @@ impl RustyNesUi {
                                 ui.label(format!("{:04X}", address));
                             }
                             if address >= start {
-                                match cpu.bus.read(address) {
+                                match cpu.bus.peek(address) {
                                     Ok(value) => ui.label(format!("{:02X}", value)),
                                     Err(_) => ui.label("--"),
                                 };
@@ -234,7 +354,7 @@ impl RustyNesUi {
                             }
                         }
                         if end == 0xFFFF {
-                            match cpu.bus.read(0xFFFF) {
+                            match cpu.bus.peek(0xFFFF) {
                                 Ok(value) => ui.label(format!("{:02X}", value)),
                                 Err(_) => ui.label("--"),
                             };
@@ -258,13 +378,25 @@ impl RustyNesUi {
                     .min_col_width(10.0)
                     .show(ui, |ui| {
                         let cpu = self.cpu.read();
+                        let debugger = self.debugger.read();
                         let mut pc = cpu.register_pc;
                         for _ in 0..20 {
+                            // Bytes the code/data logger only saw read as data aren't disassembled
+                            let flags = debugger.code_data_logger.as_ref()
+                                .and_then(|code_data_logger| code_data_logger.flags(&cpu, pc))
+                                .unwrap_or(0);
+                            if flags & (CDL_CODE | CDL_DATA) == CDL_DATA {
+                                ui.label(format!("{:04X}", pc));
+                                ui.label(format!(".db ${:02X}", cpu.bus.peek(pc).unwrap_or(0)));
+                                pc = pc.wrapping_add(1);
+                                ui.end_row();
+                                continue;
+                            }
                             let def = Instruction::default();
                             let disassembly = cpu.disassemble(pc, [
-                                cpu.bus.read(pc).unwrap_or(0),
-                                cpu.bus.read(pc.wrapping_add(1)).unwrap_or(0),
-                                cpu.bus.read(pc.wrapping_add(2)).unwrap_or(0),
+                                cpu.bus.peek(pc).unwrap_or(0),
+                                cpu.bus.peek(pc.wrapping_add(1)).unwrap_or(0),
+                                cpu.bus.peek(pc.wrapping_add(2)).unwrap_or(0),
                             ]).unwrap_or(def);
                             ui.label(format!("{:04X}", pc));
                             ui.label(disassembly.to_string());
@@ -293,11 +425,11 @@ impl RustyNesUi {
                                 ui.label("");
                             }
                             ui.label(format!("{:04X}", i));
-                            match cpu.bus.read(i) {
+                            match cpu.bus.peek(i) {
                                 Ok(value) => ui.label(format!("{:02X}", value)),
                                 Err(_) => ui.label("--"),
                             };
-                            match cpu.bus.read_word(i) {
+                            match cpu.bus.peek_word(i) {
                                 Ok(value) => ui.label(format!("{:04X}", value)),
                                 Err(_) => ui.label("--"),
                             };
@@ -307,6 +439,181 @@ impl RustyNesUi {
             });
     }
 
+    fn draw_call_stack_window(&mut self, ctx: &Context) {
+        egui::Window::new("Call Stack")
+            .resizable(true)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let debugger = self.debugger.read();
+                egui::Grid::new("call_stack_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Caller");
+                        ui.label("Target");
+                        ui.label("Return");
+                        ui.label("");
+                        ui.end_row();
+                        for frame in debugger.call_stack.frames().iter().rev() {
+                            ui.label(format!("{:04X}", frame.caller));
+                            ui.label(format!("{:04X}", frame.target));
+                            ui.label(format!("{:04X}", frame.return_address));
+                            ui.label(if frame.interrupt { "Interrupt" } else { "" });
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                for warning in debugger.call_stack.warnings().rev().take(10) {
+                    ui.colored_label(Color32::YELLOW, warning.to_string());
+                }
+            });
+    }
+
+    fn draw_profiler_window(&mut self, ctx: &Context) {
+        egui::Window::new("Profiler")
+            .resizable(true)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let mut debugger = self.debugger.write();
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut debugger.profiler.enabled, "Enabled");
+                    if ui.button("Clear").clicked() {
+                        debugger.profiler.clear();
+                    }
+                    if ui.button("Save Flamegraph").clicked() {
+                        self.profile_error = fs::write(PROFILE_PATH, debugger.profiler.collapsed_stacks())
+                            .map_err(|e| e.to_string())
+                            .err();
+                    }
+                });
+                if let Some(error) = &self.profile_error {
+                    ui.colored_label(Color32::LIGHT_RED, error);
+                }
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.profile_functions, false, "Addresses");
+                    ui.radio_value(&mut self.profile_functions, true, "Functions");
+                });
+
+                // Rows are the name followed by instructions, cycles and total cycles
+                let mut rows: Vec<(String, [u64; 3])> = if self.profile_functions {
+                    debugger.profiler.functions().iter()
+                        .map(|cost| (cost.function.to_string(), [
+                            cost.self_cost.instructions,
+                            cost.self_cost.cycles,
+                            cost.total_cost.cycles,
+                        ]))
+                        .collect()
+                } else {
+                    debugger.profiler.addresses().iter()
+                        .map(|(address, cost)| (format!("{:04X}", address), [cost.instructions, cost.cycles, cost.cycles]))
+                        .collect()
+                };
+                match self.profile_sort_column {
+                    0 => rows.sort_by(|a, b| a.0.cmp(&b.0)),
+                    column => rows.sort_by(|a, b| b.1[column - 1].cmp(&a.1[column - 1])),
+                }
+
+                egui::Grid::new("profiler_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let headers = if self.profile_functions {
+                            ["Function", "Instructions", "Cycles", "Total Cycles"]
+                        } else {
+                            ["Address", "Instructions", "Cycles", ""]
+                        };
+                        for (column, header) in headers.iter().enumerate() {
+                            if !header.is_empty() && ui.selectable_label(self.profile_sort_column == column, *header).clicked() {
+                                self.profile_sort_column = column;
+                            }
+                        }
+                        ui.end_row();
+                        for (name, costs) in rows.iter().take(100) {
+                            ui.label(name);
+                            for cost in &costs[..if self.profile_functions { 3 } else { 2 }] {
+                                ui.label(cost.to_string());
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    fn draw_code_data_logger_window(&mut self, ctx: &Context) {
+        egui::Window::new("Code/Data Logger")
+            .resizable(false)
+            .vscroll(false)
+            .show(ctx, |ui| {
+                let mut debugger = self.debugger.write();
+                let code_data_logger = debugger.code_data_logger.as_mut().unwrap();
+                let (code, data) = code_data_logger.coverage();
+                ui.label(format!("Code: {} bytes", code));
+                ui.label(format!("Data: {} bytes", data));
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        self.code_data_log_error = fs::write(&self.code_data_log_path, code_data_logger.to_bytes())
+                            .map_err(|e| e.to_string())
+                            .err();
+                    }
+                    if ui.button("Load").clicked() {
+                        self.code_data_log_error = fs::read(&self.code_data_log_path)
+                            .map_err(|e| e.to_string())
+                            .and_then(|bytes| code_data_logger.load(&bytes).map_err(|e| e.to_string()))
+                            .err();
+                    }
+                    if ui.button("Clear").clicked() {
+                        code_data_logger.clear();
+                    }
+                });
+                if let Some(error) = &self.code_data_log_error {
+                    ui.colored_label(Color32::LIGHT_RED, error);
+                }
+            });
+    }
+
+    fn draw_trace_logger_window(&mut self, ctx: &Context) {
+        egui::Window::new("Trace Logger")
+            .resizable(false)
+            .vscroll(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.trace_format, TraceFormat::Nestest, "nestest");
+                    ui.radio_value(&mut self.trace_format, TraceFormat::Mesen, "Mesen");
+                    ui.radio_value(&mut self.trace_format, TraceFormat::Fceux, "FCEUX");
+                    ui.radio_value(&mut self.trace_format, TraceFormat::Binary, "Binary");
+                });
+                let old_start_address = self.trace_start_address.clone();
+                let old_end_address = self.trace_end_address.clone();
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.trace_pc_range, "PC");
+                    ui.text_edit_singleline(&mut self.trace_start_address);
+                    ui.text_edit_singleline(&mut self.trace_end_address);
+                });
+                validate_word(&mut self.trace_start_address, old_start_address);
+                validate_word(&mut self.trace_end_address, old_end_address);
+                let old_start_frame = self.trace_start_frame.clone();
+                let old_end_frame = self.trace_end_frame.clone();
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.trace_frame_range, "Frames");
+                    ui.text_edit_singleline(&mut self.trace_start_frame);
+                    ui.text_edit_singleline(&mut self.trace_end_frame);
+                });
+                validate_number(&mut self.trace_start_frame, old_start_frame);
+                validate_number(&mut self.trace_end_frame, old_end_frame);
+                ui.horizontal(|ui| {
+                    ui.label("Trigger");
+                    ui.text_edit_singleline(&mut self.trace_trigger);
+                });
+                let old_ring_size = self.trace_ring_size.clone();
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.trace_ring, "Ring Bytes");
+                    ui.text_edit_singleline(&mut self.trace_ring_size);
+                });
+                validate_number(&mut self.trace_ring_size, old_ring_size);
+                if let Some(error) = &self.trace_error {
+                    ui.colored_label(Color32::LIGHT_RED, error);
+                }
+            });
+    }
+
     fn draw_memory_write_window(&mut self, ctx: &Context) {
         egui::Window::new("Memory Write")
             .resizable(false)
@@ -326,11 +633,124 @@ impl RustyNesUi {
                 let value = validate_byte(&mut self.memory_write_value, old_value);
 
                 if ui.button("Write").clicked() {
-                    self.cpu.write().bus.write(address, value);
+                    let _ = self.cpu.write().bus.write(address, value);
+                }
+            });
+    }
+
+    fn draw_breakpoints_window(&mut self, ctx: &Context) {
+        egui::Window::new("Breakpoints")
+            .resizable(false)
+            .vscroll(false)
+            .show(ctx, |ui| {
+                let old_breakpoint_address = self.breakpoint_address.clone();
+                let old_start_address = self.watchpoint_start_address.clone();
+                let old_end_address = self.watchpoint_end_address.clone();
+                ui.horizontal(|ui| {
+                    ui.label("Condition");
+                    ui.text_edit_singleline(&mut self.condition);
+                });
+                if let Some(error) = &self.condition_error {
+                    ui.colored_label(Color32::LIGHT_RED, error);
+                }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Address");
+                    ui.text_edit_singleline(&mut self.breakpoint_address);
+                });
+                let breakpoint_address = validate_word(&mut self.breakpoint_address, old_breakpoint_address);
+                if ui.button("Add Breakpoint").clicked() {
+                    if let Some(condition) = self.parse_condition() {
+                        self.debugger.write().breakpoints.push(Breakpoint {
+                            address: breakpoint_address,
+                            condition,
+                        });
+                    }
+                }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Start Address");
+                    ui.text_edit_singleline(&mut self.watchpoint_start_address);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("End Address");
+                    ui.text_edit_singleline(&mut self.watchpoint_end_address);
+                });
+                let start = validate_word(&mut self.watchpoint_start_address, old_start_address);
+                let end = validate_word(&mut self.watchpoint_end_address, old_end_address);
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.watchpoint_read, "Read");
+                    ui.checkbox(&mut self.watchpoint_write, "Write");
+                    ui.checkbox(&mut self.watchpoint_execute, "Execute");
+                });
+                if ui.button("Add Watchpoint").clicked() {
+                    if let Some(condition) = self.parse_condition() {
+                        self.debugger.write().watchpoints.push(Watchpoint {
+                            range: start..=end,
+                            read: self.watchpoint_read,
+                            write: self.watchpoint_write,
+                            execute: self.watchpoint_execute,
+                            condition,
+                        });
+                    }
                 }
+                ui.separator();
+
+                let mut debugger = self.debugger.write();
+                egui::Grid::new("breakpoint_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let mut removed = None;
+                        for (i, breakpoint) in debugger.breakpoints.iter().enumerate() {
+                            ui.label(format!("PC == {:04X}", breakpoint.address));
+                            ui.label(breakpoint.condition.as_ref().map_or(String::new(), |c| c.to_string()));
+                            if ui.button("Remove").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                        if let Some(i) = removed {
+                            debugger.breakpoints.remove(i);
+                        }
+
+                        let mut removed = None;
+                        for (i, watchpoint) in debugger.watchpoints.iter().enumerate() {
+                            let kinds = [(watchpoint.read, 'R'), (watchpoint.write, 'W'), (watchpoint.execute, 'X')]
+                                .iter()
+                                .map(|&(enabled, kind)| if enabled { kind } else { '-' })
+                                .collect::<String>();
+                            ui.label(format!("{} {:04X}-{:04X}", kinds, watchpoint.range.start(), watchpoint.range.end()));
+                            ui.label(watchpoint.condition.as_ref().map_or(String::new(), |c| c.to_string()));
+                            if ui.button("Remove").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                        if let Some(i) = removed {
+                            debugger.watchpoints.remove(i);
+                        }
+                    });
             });
     }
 
+    // An empty condition always holds
+    fn parse_condition(&mut self) -> Option<Option<Condition>> {
+        self.condition_error = None;
+        if self.condition.trim().is_empty() {
+            return Some(None);
+        }
+        match Condition::parse(&self.condition) {
+            Ok(condition) => Some(Some(condition)),
+            Err(e) => {
+                self.condition_error = Some(e.to_string());
+                None
+            }
+        }
+    }
+
+    #[allow(dead_code)]
     fn draw_display_window(&mut self, ctx: &Context) {
         egui::Window::new("Display")
             .resizable(false)
@@ -346,7 +766,7 @@ impl RustyNesUi {
 
                 for row in 0..32 {
                     for col in 0..32 {
-                        let color_code = cpu.bus.read(0x0200 + row * 32 + col).unwrap();
+                        let color_code = cpu.bus.peek(0x0200 + row * 32 + col).unwrap();
                         let color = match color_code & 0xF {
                             0x00 => Color32::BLACK,
                             0x01 => Color32::WHITE,
@@ -386,6 +806,7 @@ impl RustyNesUi {
             });
     }
 
+    #[allow(dead_code)]
     fn handle_input(&mut self, ctx: &Context) {
         if self.stop_tx.is_some() {
             if ctx.input().key_pressed(Key::W) {
@@ -400,42 +821,86 @@ impl RustyNesUi {
         }
     }
 
-    fn create_run_thread(&mut self, save_trace: bool) {
+    fn run_until(&mut self, target: Option<RunTarget>, save_trace: bool) {
+        self.stop_reason = None;
+        let trace_logger = if save_trace {
+            match self.create_trace_logger() {
+                Ok(trace_logger) => Some(trace_logger),
+                Err(e) => {
+                    self.trace_error = Some(e);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        self.debugger.write().target = target;
+        self.create_run_thread(trace_logger);
+    }
+
+    fn create_trace_logger(&mut self) -> Result<TraceLogger, String> {
+        self.trace_error = None;
+        let trigger = if self.trace_trigger.trim().is_empty() {
+            None
+        } else {
+            Some(Condition::parse(&self.trace_trigger).map_err(|e| e.to_string())?)
+        };
+        let limit = if self.trace_ring {
+            TraceLimit::Ring(self.trace_ring_size.parse().unwrap())
+        } else {
+            TraceLimit::Unlimited
+        };
+        let mut trace_logger = TraceLogger::create(TRACE_LOG_PATH, self.trace_format, limit)
+            .map_err(|e| e.to_string())?;
+        trace_logger.filter = TraceFilter {
+            pc_range: self.trace_pc_range.then(|| {
+                u16::from_str_radix(&self.trace_start_address, 16).unwrap()
+                    ..=u16::from_str_radix(&self.trace_end_address, 16).unwrap()
+            }),
+            frame_range: self.trace_frame_range.then(|| {
+                self.trace_start_frame.parse().unwrap()..=self.trace_end_frame.parse().unwrap()
+            }),
+            trigger,
+        };
+        Ok(trace_logger)
+    }
+
+    fn create_run_thread(&mut self, mut trace_logger: Option<TraceLogger>) {
         let cpu = self.cpu.clone();
+        let debugger = self.debugger.clone();
         let (stop_tx, stop_rx) = mpsc::channel();
         let (halted_tx, halted_rx) = mpsc::channel();
         self.stop_tx = Some(stop_tx);
         self.halted_rx = Some(halted_rx);
         thread::spawn(move || {
-            let mut trace_vec = Vec::new();
             'main: loop {
                 if stop_rx.try_recv().is_ok() {
                     break;
                 }
                 for _ in 0..300 {
                     let mut cpu_lock = cpu.write();
-                    if save_trace {
-                        trace_vec.push(cpu_lock.trace());
+                    // The run goes on without the trace if the file can't be written
+                    if trace_logger.as_mut().is_some_and(|trace_logger| trace_logger.log(&*cpu_lock).is_err()) {
+                        trace_logger = None;
                     }
-                    match cpu_lock.step() {
-                        Ok(halted) => {
-                            if halted {
-                                halted_tx.send(()).unwrap();
+                    // Checked after stepping so resuming runs the instruction it stopped on
+                    match debugger.write().step(&mut *cpu_lock) {
+                        Ok(stop_reason) => {
+                            if cpu_lock.halted || stop_reason.is_some() {
+                                halted_tx.send(stop_reason).unwrap();
                                 break 'main;
                             }
                         }
                         Err(_) => {
-                            halted_tx.send(()).unwrap();
+                            halted_tx.send(None).unwrap();
                             break 'main;
                         }
                     }
                 }
                 thread::sleep(Duration::from_nanos(1_000_000_000 / 60));
             }
-            if save_trace {
-                let mut to_write = trace_vec.iter().map(|trace| trace.to_string()).collect::<Vec<String>>().join("\n");
-                to_write.push('\n');
-                fs::write("log", to_write).unwrap();
+            if let Some(mut trace_logger) = trace_logger {
+                let _ = trace_logger.flush();
             }
         });
     }
@@ -455,4 +920,11 @@ fn validate_byte(to_validate: &mut String, old: String) -> u8 {
         to_validate.push_str(&old);
         u8::from_str_radix(to_validate, 16).unwrap()
     })
-}
\ No newline at end of file
+}
+fn validate_number(to_validate: &mut String, old: String) -> u64 {
+    to_validate.parse().unwrap_or_else(|_| {
+        to_validate.clear();
+        to_validate.push_str(&old);
+        to_validate.parse().unwrap()
+    })
+}
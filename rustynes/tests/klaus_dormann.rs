@@ -0,0 +1,64 @@
+//! Runs Klaus Dormann's 6502 functional test and the decimal mode test, which trap in a loop at
+//! a known address on success and anywhere else on failure.
+//!
+//! The binaries aren't part of the repository, so the tests are ignored by default and run with
+//! `cargo test --test klaus_dormann -- --ignored`. They are read from `tests/klaus`, as assembled
+//! with the default configuration, and `KLAUS_FUNCTIONAL_TEST`, `KLAUS_SUCCESS_ADDRESS` and
+//! `KLAUS_DECIMAL_TEST` point at other builds.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use rustynes::cpu::trap::TrapReport;
+use rustynes::cpu::{Cpu, CpuVariant};
+use rustynes::memory::flat::FlatBus;
+use rustynes::memory::Bus;
+
+const FUNCTIONAL_TEST: &str = "tests/klaus/6502_functional_test.bin";
+const FUNCTIONAL_TEST_START: u16 = 0x0400;
+const FUNCTIONAL_TEST_SUCCESS: u16 = 0x3469;
+
+const DECIMAL_TEST: &str = "tests/klaus/6502_decimal_test.bin";
+const DECIMAL_TEST_START: u16 = 0x0200;
+// Cleared when every result matched
+const DECIMAL_TEST_ERROR: u16 = 0x000B;
+
+const MAX_INSTRUCTIONS: u64 = 100_000_000;
+const TRACE_LENGTH: usize = 32;
+
+fn load_test(variable: &str, default: &str, address: u16) -> Cpu<FlatBus> {
+    let path = PathBuf::from(env::var(variable).unwrap_or_else(|_| default.to_string()));
+    let binary = fs::read(&path)
+        .unwrap_or_else(|e| panic!("Can't read {}: {}, set {}", path.display(), e, variable));
+    let mut bus = FlatBus::new();
+    bus.load(address, &binary);
+    let mut cpu = Cpu::new(bus);
+    cpu.variant = CpuVariant::Nmos6502;
+    cpu
+}
+
+fn run(cpu: &mut Cpu<FlatBus>, start: u16) -> TrapReport {
+    cpu.register_pc = start;
+    let report = cpu.run_until_trap(MAX_INSTRUCTIONS, TRACE_LENGTH);
+    assert!(report.trapped, "{}", report);
+    report
+}
+
+#[test]
+#[ignore = "needs the assembled test binaries, see the module docs"]
+fn test_functional() {
+    let mut cpu = load_test("KLAUS_FUNCTIONAL_TEST", FUNCTIONAL_TEST, 0x0000);
+    let success = env::var("KLAUS_SUCCESS_ADDRESS")
+        .map(|address| u16::from_str_radix(address.trim_start_matches('$'), 16).unwrap())
+        .unwrap_or(FUNCTIONAL_TEST_SUCCESS);
+    let report = run(&mut cpu, FUNCTIONAL_TEST_START);
+    assert_eq!(report.address, success, "{}", report);
+}
+
+#[test]
+#[ignore = "needs the assembled test binaries, see the module docs"]
+fn test_decimal() {
+    let mut cpu = load_test("KLAUS_DECIMAL_TEST", DECIMAL_TEST, DECIMAL_TEST_START);
+    let report = run(&mut cpu, DECIMAL_TEST_START);
+    assert_eq!(cpu.bus.peek(DECIMAL_TEST_ERROR).unwrap(), 0, "{}", report);
+}
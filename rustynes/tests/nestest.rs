@@ -0,0 +1,85 @@
+//! Runs nestest in automation mode and compares the trace with the reference log from Nintendulator.
+
+use std::fs;
+use std::path::Path;
+use rustynes::cpu::Cpu;
+use rustynes::EmulationError;
+use rustynes::memory::mapped::Device;
+use rustynes::memory::nes::NesBus;
+use rustynes::memory::Bus;
+use rustynes::rom::Rom;
+
+// Automation mode starts here instead of at the reset vector, and needs no PPU.
+const AUTOMATION_START: u16 = 0xC000;
+// nestest ends by writing to the APU registers, which aren't emulated yet.
+const APU_REGISTERS_START: u16 = 0x4000;
+const APU_REGISTERS_END: u16 = 0x401F;
+// nestest leaves the number of the first failed official and unofficial test in these.
+const OFFICIAL_RESULT: u16 = 0x0002;
+const UNOFFICIAL_RESULT: u16 = 0x0003;
+const CONTEXT_LINES: usize = 5;
+
+// Stands in for the APU registers, which read as $FF in the reference log.
+struct ApuRegisters;
+
+impl Device for ApuRegisters {
+    fn peek(&self, _address: u16) -> Result<u8, EmulationError> {
+        Ok(0xFF)
+    }
+
+    fn write(&mut self, _address: u16, _value: u8) -> Result<(), EmulationError> {
+        Ok(())
+    }
+}
+
+fn nestest_log(root: &Path) -> Vec<String> {
+    fs::read_to_string(root.join("logs/nestest.log"))
+        .unwrap()
+        .lines()
+        .map(|line| {
+            // We have no PPU yet, so drop its column
+            let ppu = line.find(" PPU:").unwrap();
+            let cyc = line.find(" CYC:").unwrap();
+            format!("{}{}", &line[..ppu], &line[cyc..])
+        })
+        .collect()
+}
+
+// Shows the lines leading to a mismatch, and marks the column it starts at.
+fn mismatch(log: &[String], number: usize, actual: &str) -> String {
+    let expected = &log[number];
+    let column = expected.chars().zip(actual.chars())
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    let mut diff = format!("Trace differs from logs/nestest.log at line {}:\n", number + 1);
+    for (i, line) in log.iter().enumerate().take(number).skip(number.saturating_sub(CONTEXT_LINES)) {
+        diff.push_str(&format!("  {:5} {}\n", i + 1, line));
+    }
+    diff.push_str(&format!("- {:5} {}\n", number + 1, expected));
+    diff.push_str(&format!("+ {:5} {}\n", number + 1, actual));
+    diff.push_str(&format!("        {}^", " ".repeat(column)));
+    diff
+}
+
+#[test]
+fn test_nestest() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let rom = Rom::new(&fs::read(root.join("roms/nestest.nes")).unwrap()).unwrap();
+    let mut cpu = Cpu::new(NesBus::new(rom));
+    cpu.bus.map(APU_REGISTERS_START..=APU_REGISTERS_END, 0x001F, ApuRegisters).unwrap();
+    cpu.register_pc = AUTOMATION_START;
+
+    let log = nestest_log(&root);
+    for number in 0..log.len() {
+        let actual = cpu.trace().to_string();
+        if actual != log[number] {
+            panic!("{}", mismatch(&log, number, &actual));
+        }
+        if let Err(e) = cpu.step() {
+            panic!("Line {} failed to run: {}\n{}", number + 1, e, log[number]);
+        }
+    }
+
+    assert_eq!(cpu.bus.peek(OFFICIAL_RESULT).unwrap(), 0x00, "official opcode test failed");
+    assert_eq!(cpu.bus.peek(UNOFFICIAL_RESULT).unwrap(), 0x00, "unofficial opcode test failed");
+}
@@ -0,0 +1,218 @@
+//! Runs the per-instruction JSON vectors from the SingleStepTests ProcessorTests project, which
+//! give the state before and after a single instruction and the bus activity on every cycle.
+//!
+//! The vectors aren't part of the repository, so the test is ignored by default. Point
+//! `SINGLE_STEP_TESTS_DIR` at one of their directories, such as `nes6502/v1`, and run it with
+//! `cargo test --test single_step -- --ignored`. The variant is picked from the path. Set
+//! `SINGLE_STEP_TESTS_LIMIT` to only run the first vectors of each opcode.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use rustynes::cpu::cycle::AccessKind;
+use rustynes::cpu::{Cpu, CpuStatus, CpuVariant};
+use rustynes::memory::flat::FlatBus;
+use rustynes::memory::Bus;
+use serde_json::Value;
+
+const DEFAULT_DIR: &str = "tests/single_step/nes6502/v1";
+
+// The NMOS opcodes that aren't emulated to match the vectors: JAM, which halts instead of
+// hammering the bus, and the unstable ones, whose results depend on the chip.
+const SKIPPED_NMOS_OPCODES: &[u8] = &[
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+    0x8B, 0x93, 0x9B, 0x9C, 0x9E, 0x9F, 0xAB, 0xBB,
+];
+
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+type Cycle = (u16, u8, &'static str);
+
+// How one opcode's vectors went, with the first mismatch found.
+#[derive(Default)]
+struct OpcodeReport {
+    vectors: usize,
+    failures: usize,
+    register_failures: usize,
+    memory_failures: usize,
+    cycle_failures: usize,
+    first_failure: Option<String>,
+}
+
+#[test]
+#[ignore = "needs the SingleStepTests vectors, see the module docs"]
+fn test_single_step_vectors() {
+    let dir = env::var("SINGLE_STEP_TESTS_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string());
+    let dir = Path::new(&dir);
+    assert!(dir.is_dir(), "{} not found, set SINGLE_STEP_TESTS_DIR", dir.display());
+    let variant = variant_for(dir);
+    let limit = env::var("SINGLE_STEP_TESTS_LIMIT").ok().map(|limit| limit.parse::<usize>().unwrap());
+
+    let mut reports = BTreeMap::new();
+    for opcode in 0..=0xFFu8 {
+        let path = dir.join(format!("{:02x}.json", opcode));
+        let skipped = variant != CpuVariant::Cmos65C02 && SKIPPED_NMOS_OPCODES.contains(&opcode);
+        if skipped || !path.exists() {
+            continue;
+        }
+        let vectors: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let vectors = vectors.as_array().unwrap();
+        let mut report = OpcodeReport::default();
+        for vector in vectors.iter().take(limit.unwrap_or(usize::MAX)) {
+            let failures = report.register_failures + report.memory_failures + report.cycle_failures;
+            run_vector(variant, vector, &mut report);
+            if report.register_failures + report.memory_failures + report.cycle_failures > failures {
+                report.failures += 1;
+            }
+        }
+        reports.insert(opcode, report);
+    }
+    assert!(!reports.is_empty(), "No vectors found in {}", dir.display());
+
+    let failing: Vec<String> = reports.iter()
+        .filter(|(_, report)| report.first_failure.is_some())
+        .map(|(opcode, report)| format!(
+            "${:02X}: {}/{} failed (registers {}, memory {}, cycles {}), first: {}",
+            opcode,
+            report.failures,
+            report.vectors,
+            report.register_failures,
+            report.memory_failures,
+            report.cycle_failures,
+            report.first_failure.as_ref().unwrap(),
+        ))
+        .collect();
+    assert!(failing.is_empty(), "{} of {} opcodes failed:\n{}", failing.len(), reports.len(), failing.join("\n"));
+}
+
+fn variant_for(dir: &Path) -> CpuVariant {
+    let path = dir.to_string_lossy().to_lowercase();
+    if path.contains("65c02") {
+        CpuVariant::Cmos65C02
+    } else if path.contains("nes6502") {
+        CpuVariant::Nes2A03
+    } else {
+        CpuVariant::Nmos6502
+    }
+}
+
+fn run_vector(variant: CpuVariant, vector: &Value, report: &mut OpcodeReport) {
+    let name = vector["name"].as_str().unwrap_or("?");
+    let initial = parse_state(&vector["initial"]);
+    let expected = parse_state(&vector["final"]);
+    let expected_cycles = parse_cycles(&vector["cycles"]);
+    report.vectors += 1;
+
+    let mut bus = FlatBus::new();
+    for &(address, value) in &initial.ram {
+        bus.write(address, value).unwrap();
+    }
+    let mut cpu = Cpu::new(bus);
+    cpu.variant = variant;
+    cpu.register_pc = initial.pc;
+    cpu.register_sp = initial.s;
+    cpu.register_a = initial.a;
+    cpu.register_x = initial.x;
+    cpu.register_y = initial.y;
+    cpu.status_flags = CpuStatus { status: initial.p };
+
+    let mut cycles = Vec::new();
+    loop {
+        match cpu.tick() {
+            Ok(access) => cycles.push((access.address, access.value, match access.kind {
+                AccessKind::Write | AccessKind::DummyWrite => "write",
+                _ => "read",
+            })),
+            Err(e) => {
+                fail(report, |report| &mut report.register_failures, || format!("{}: {}", name, e));
+                return;
+            }
+        }
+        if !cpu.mid_instruction() {
+            break;
+        }
+    }
+
+    let registers = [cpu.register_pc, cpu.register_sp as u16, cpu.register_a as u16, cpu.register_x as u16,
+        cpu.register_y as u16, cpu.status_flags.status as u16];
+    let expected_registers = [expected.pc, expected.s as u16, expected.a as u16, expected.x as u16,
+        expected.y as u16, expected.p as u16];
+    if registers != expected_registers {
+        fail(report, |report| &mut report.register_failures, || format!(
+            "{}: PC/S/A/X/Y/P are {} instead of {}",
+            name, format_registers(&registers), format_registers(&expected_registers),
+        ));
+    }
+
+    let wrong_memory: Vec<String> = expected.ram.iter()
+        .filter_map(|&(address, value)| {
+            let actual = cpu.bus.peek(address).unwrap();
+            (actual != value).then(|| format!("${:04X} is ${:02X} instead of ${:02X}", address, actual, value))
+        })
+        .collect();
+    if !wrong_memory.is_empty() {
+        fail(report, |report| &mut report.memory_failures, || format!("{}: {}", name, wrong_memory.join(", ")));
+    }
+
+    if cycles != expected_cycles {
+        fail(report, |report| &mut report.cycle_failures, || format!(
+            "{}: cycles are {} instead of {}",
+            name, format_cycles(&cycles), format_cycles(&expected_cycles),
+        ));
+    }
+}
+
+fn fail(report: &mut OpcodeReport, counter: fn(&mut OpcodeReport) -> &mut usize, message: impl FnOnce() -> String) {
+    *counter(report) += 1;
+    if report.first_failure.is_none() {
+        report.first_failure = Some(message());
+    }
+}
+
+fn parse_state(state: &Value) -> State {
+    let number = |key: &str| state[key].as_u64().unwrap();
+    State {
+        pc: number("pc") as u16,
+        s: number("s") as u8,
+        a: number("a") as u8,
+        x: number("x") as u8,
+        y: number("y") as u8,
+        p: number("p") as u8,
+        ram: state["ram"].as_array().unwrap().iter()
+            .map(|entry| (entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8))
+            .collect(),
+    }
+}
+
+fn parse_cycles(cycles: &Value) -> Vec<Cycle> {
+    cycles.as_array().unwrap().iter()
+        .map(|cycle| {
+            let kind = match cycle[2].as_str().unwrap() {
+                "write" => "write",
+                _ => "read",
+            };
+            (cycle[0].as_u64().unwrap() as u16, cycle[1].as_u64().unwrap() as u8, kind)
+        })
+        .collect()
+}
+
+fn format_registers(registers: &[u16; 6]) -> String {
+    format!("{:04X}/{:02X}/{:02X}/{:02X}/{:02X}/{:02X}",
+        registers[0], registers[1], registers[2], registers[3], registers[4], registers[5])
+}
+
+fn format_cycles(cycles: &[Cycle]) -> String {
+    cycles.iter()
+        .map(|(address, value, kind)| format!("{} ${:04X}=${:02X}", kind, address, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
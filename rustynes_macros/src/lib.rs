@@ -2,21 +2,35 @@
 mod test;
 
 use proc_macro2::{Ident, TokenStream};
-use quote::quote;
-use syn::{braced, Expr, parenthesized, Token};
+use quote::{quote, quote_spanned};
+use syn::{braced, bracketed, Expr, LitInt, parenthesized, Token};
 use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 
-/// $TO_MATCH { $($INSTRUCTION: $($OPCODE => MODE ($BYTES)),+)+; }
+/// $TO_MATCH { $($(*)?$INSTRUCTION: $($OPCODE => MODE ($BYTES) [$CYCLES]),+)+; } $(else $FALLBACK)?
+///
+/// Instructions prefixed with `*` are unofficial opcodes. Opcodes missing from the set evaluate
+/// `$FALLBACK` if given, and are invalid otherwise.
 struct InstructionSetCallMatch {
     to_match: Ident,
     instructions: InstructionSet,
+    fallback: Option<Expr>,
 }
 
 struct InstructionSetDisassemblyMatch {
     operands: Ident,
     address: Ident,
     instructions: InstructionSet,
+    fallback: Option<Expr>,
+}
+
+/// { $($(*)?$INSTRUCTION: $($OPCODE => MODE ($BYTES) [$CYCLES]),+)+; } $(else $FALLBACK)?
+///
+/// Builds a `[Option<OpcodeInfo>; 256]` indexed by opcode. Opcodes missing from the set are taken
+/// from the `$FALLBACK` table if given, and are `None` otherwise.
+struct InstructionSetTable {
+    instructions: InstructionSet,
+    fallback: Option<Expr>,
 }
 
 struct InstructionSet {
@@ -24,25 +38,48 @@ struct InstructionSet {
 }
 
 struct Instruction {
+    unofficial: bool,
     instruction: Ident,
     opcodes: Punctuated<Opcode, Token![,]>,
 }
 
 struct Opcode {
-    opcode: Expr,
+    opcode: LitInt,
     mode: Ident,
-    bytes: Expr,
+    bytes: LitInt,
+    cycles: LitInt,
 }
 
+// The addressing modes the CPU knows, with the length of their instructions in bytes.
+const ADDRESSING_MODES: &[(&str, u8)] = &[
+    ("Immediate", 2),
+    ("ZeroPage", 2),
+    ("ZeroPageX", 2),
+    ("ZeroPageY", 2),
+    ("Absolute", 3),
+    ("AbsoluteX", 3),
+    ("AbsoluteY", 3),
+    ("Indirect", 3),
+    ("IndirectX", 2),
+    ("IndirectY", 2),
+    ("Relative", 2),
+    ("Accumulator", 1),
+    ("Implied", 1),
+    ("ZeroPageIndirect", 2),
+    ("AbsoluteIndexedIndirect", 3),
+];
+
 impl Parse for InstructionSetCallMatch {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let to_match = input.parse()?;
         let content;
         braced!(content in input);
         let instructions = content.parse()?;
+        let fallback = parse_fallback(input)?;
         Ok(InstructionSetCallMatch {
             to_match,
             instructions,
+            fallback,
         })
     }
 }
@@ -55,27 +92,73 @@ impl Parse for InstructionSetDisassemblyMatch {
         let content;
         braced!(content in input);
         let instructions = content.parse()?;
+        let fallback = parse_fallback(input)?;
         Ok(InstructionSetDisassemblyMatch {
             operands,
             address,
             instructions,
+            fallback,
+        })
+    }
+}
+
+impl Parse for InstructionSetTable {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let content;
+        braced!(content in input);
+        let instructions = content.parse()?;
+        let fallback = parse_fallback(input)?;
+        Ok(InstructionSetTable {
+            instructions,
+            fallback,
         })
     }
 }
 
+fn parse_fallback(input: ParseStream) -> syn::Result<Option<Expr>> {
+    if input.peek(Token![else]) {
+        input.parse::<Token![else]>()?;
+        Ok(Some(input.parse()?))
+    } else {
+        Ok(None)
+    }
+}
+
 impl Parse for InstructionSet {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let instructions = Punctuated::<Instruction, Token![;]>::parse_terminated(input)?;
+
+        let mut defined: [Option<&LitInt>; 256] = [None; 256];
+        let mut errors: Option<syn::Error> = None;
+        for opcode in instructions.iter().flat_map(|instruction| &instruction.opcodes) {
+            let value = opcode.opcode.base10_parse::<u8>()? as usize;
+            if let Some(first) = defined[value] {
+                let mut error = syn::Error::new(opcode.opcode.span(), format!("duplicate opcode {}", opcode.opcode));
+                error.combine(syn::Error::new(first.span(), "first defined here"));
+                match &mut errors {
+                    Some(errors) => errors.combine(error),
+                    None => errors = Some(error),
+                }
+            } else {
+                defined[value] = Some(&opcode.opcode);
+            }
+        }
+        if let Some(errors) = errors {
+            return Err(errors);
+        }
+
         Ok(InstructionSet { instructions })
     }
 }
 
 impl Parse for Instruction {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let unofficial = input.parse::<Option<Token![*]>>()?.is_some();
         let instruction = input.parse()?;
         input.parse::<Token![:]>()?;
         let opcodes = Punctuated::<Opcode, Token![,]>::parse_separated_nonempty(input)?;
         Ok(Instruction {
+            unofficial,
             instruction,
             opcodes,
         })
@@ -85,38 +168,69 @@ impl Parse for Instruction {
 
 impl Parse for Opcode {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let opcode = input.parse()?;
+        let opcode: LitInt = input.parse()?;
         input.parse::<Token![=>]>()?;
-        let mode = input.parse()?;
+        let mode: Ident = input.parse()?;
         let content;
         parenthesized!(content in input);
-        let bytes = content.parse()?;
+        let bytes: LitInt = content.parse()?;
+        let content;
+        bracketed!(content in input);
+        let cycles = content.parse()?;
+
+        opcode.base10_parse::<u8>()?;
+        let Some((_, length)) = ADDRESSING_MODES.iter().find(|(name, _)| mode == name) else {
+            return Err(syn::Error::new(mode.span(), format!("unknown addressing mode `{}`", mode)));
+        };
+        if bytes.base10_parse::<u8>()? != *length {
+            return Err(syn::Error::new(
+                bytes.span(),
+                format!("{} instructions are {} bytes long, not {}", mode, length, bytes),
+            ));
+        }
 
         Ok(Opcode {
             opcode,
             mode,
             bytes,
+            cycles,
         })
     }
 }
 
 impl Instruction {
-    fn operation_match_arms(&self) -> TokenStream {
+    // `cpu` is the `self` of the method the match is expanded in. It must come from the caller's
+    // tokens, since a `self` of our own would not resolve when invoked through `macro_rules!`.
+    fn operation_match_arms(&self, cpu: &TokenStream) -> TokenStream {
         let instruction = &self.instruction;
         let opcodes = &self.opcodes;
+        // Unofficial opcodes fall through to the invalid opcode arm when they are disabled
+        let guard = if self.unofficial {
+            quote!(if #cpu.allow_unofficial_opcodes)
+        } else {
+            TokenStream::new()
+        };
 
         let mut output = TokenStream::new();
         for opcode in opcodes {
             let mode = &opcode.mode;
             let bytes = &opcode.bytes;
+            let cycles = &opcode.cycles;
+            // Instructions without operands read the byte after the opcode on their second cycle
+            let implied_read = if is_one(bytes) && !is_one(cycles) {
+                quote!(#cpu.dummy_read(#cpu.register_pc)?;)
+            } else {
+                TokenStream::new()
+            };
             let opcode = &opcode.opcode;
             output.extend(quote!{
-                #opcode => {
-                    let op_result = self.#instruction(AddressingMode::#mode)?;
+                #opcode #guard => {
+                    #implied_read
+                    let op_result = #cpu.#instruction(AddressingMode::#mode)?;
                     if op_result.increment_pc {
-                        self.register_pc = self.register_pc.wrapping_add(#bytes - 1);
+                        #cpu.register_pc = #cpu.register_pc.wrapping_add(#bytes - 1);
                     }
-                    Ok(op_result.extra_cycles)
+                    Ok(#cycles + op_result.extra_cycles)
                 },
             });
         }
@@ -127,6 +241,7 @@ impl Instruction {
     fn disassembly_match_arms(&self) -> TokenStream {
         let instruction = &self.instruction;
         let opcodes = &self.opcodes;
+        let unofficial = self.unofficial;
 
         let mut output = TokenStream::new();
         for opcode in opcodes {
@@ -142,6 +257,7 @@ impl Instruction {
                         instruction: stringify!(#instruction),
                         addressing_mode: AddressingMode::#mode,
                         length: #bytes,
+                        unofficial: #unofficial,
                     }
                     )
                 },
@@ -150,6 +266,31 @@ impl Instruction {
 
         output
     }
+
+    fn table_entries(&self) -> TokenStream {
+        let instruction = &self.instruction;
+        let opcodes = &self.opcodes;
+        let unofficial = self.unofficial;
+
+        let mut output = TokenStream::new();
+        for opcode in opcodes {
+            let mode = &opcode.mode;
+            let bytes = &opcode.bytes;
+            let cycles = &opcode.cycles;
+            let opcode = &opcode.opcode;
+            output.extend(quote!{
+                table[#opcode as usize] = Some(OpcodeInfo {
+                    mnemonic: stringify!(#instruction),
+                    addressing_mode: AddressingMode::#mode,
+                    length: #bytes,
+                    cycles: #cycles,
+                    unofficial: #unofficial,
+                });
+            });
+        }
+
+        output
+    }
 }
 
 #[proc_macro]
@@ -162,24 +303,38 @@ fn call_op2(input: TokenStream) -> TokenStream {
     let InstructionSetCallMatch {
         to_match,
         instructions: InstructionSet { instructions },
-    } = syn::parse2(input).unwrap();
+        fallback,
+    } = match syn::parse2(input) {
+        Ok(parsed) => parsed,
+        Err(error) => return error.to_compile_error(),
+    };
 
+    let cpu = quote_spanned!(to_match.span()=> self);
     let mut match_arms = TokenStream::new();
 
     for instruction in instructions {
-        match_arms.extend(instruction.operation_match_arms());
+        match_arms.extend(instruction.operation_match_arms(&cpu));
     }
 
+    let fallback = fallback.map_or_else(
+        || quote!(Err(EmulationError::InvalidOpcode(#to_match))),
+        |fallback| quote!(#fallback),
+    );
+
     let output = quote! {
         match #to_match {
             #match_arms
-            _ => Err(EmulationError::InvalidOpcode(opcode)),
+            _ => #fallback,
         }
     };
 
     output
 }
 
+fn is_one(literal: &LitInt) -> bool {
+    literal.base10_parse::<u8>().is_ok_and(|value| value == 1)
+}
+
 #[proc_macro]
 pub fn disassemble_op(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let output = disassemble_op2(TokenStream::from(input));
@@ -191,7 +346,11 @@ fn disassemble_op2(input: TokenStream) -> TokenStream {
         operands,
         address,
         instructions: InstructionSet { instructions },
-    } = syn::parse2(input).unwrap();
+        fallback,
+    } = match syn::parse2(input) {
+        Ok(parsed) => parsed,
+        Err(error) => return error.to_compile_error(),
+    };
 
     let mut match_arms = TokenStream::new();
 
@@ -199,13 +358,18 @@ fn disassemble_op2(input: TokenStream) -> TokenStream {
         match_arms.extend(instruction.disassembly_match_arms());
     }
 
+    let fallback = fallback.map_or_else(
+        || quote!(Err(EmulationError::InvalidOpcode(operands[0]))),
+        |fallback| quote!(#fallback),
+    );
+
     let output = quote! {
         {
             let operands = #operands;
             let address = #address;
             match operands[0] {
                 #match_arms
-                _ => Err(EmulationError::InvalidOpcode(operands[0])),
+                _ => #fallback,
             }
         }
     };
@@ -213,3 +377,39 @@ fn disassemble_op2(input: TokenStream) -> TokenStream {
     output
 }
 
+#[proc_macro]
+pub fn opcode_table(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let output = opcode_table2(TokenStream::from(input));
+    proc_macro::TokenStream::from(output)
+}
+
+fn opcode_table2(input: TokenStream) -> TokenStream {
+    let InstructionSetTable {
+        instructions: InstructionSet { instructions },
+        fallback,
+    } = match syn::parse2(input) {
+        Ok(parsed) => parsed,
+        Err(error) => return error.to_compile_error(),
+    };
+
+    let mut entries = TokenStream::new();
+
+    for instruction in instructions {
+        entries.extend(instruction.table_entries());
+    }
+
+    let fallback = fallback.map_or_else(
+        || quote!([None; 256]),
+        |fallback| quote!(#fallback),
+    );
+
+    let output = quote! {
+        {
+            let mut table: [Option<OpcodeInfo>; 256] = #fallback;
+            #entries
+            table
+        }
+    };
+
+    output
+}
@@ -0,0 +1,155 @@
+use quote::quote;
+use crate::{call_op2, disassemble_op2, opcode_table2};
+
+#[test]
+fn test_call_op_adds_base_cycles() {
+    let output = call_op2(quote! {
+        opcode {
+            lda: 0xA9 => Immediate (2) [2], 0xBD => AbsoluteX (3) [4];
+        }
+    }).to_string();
+    assert!(output.contains("0xA9 =>"));
+    assert!(output.contains("Ok (2 + op_result . extra_cycles)"));
+    assert!(output.contains("Ok (4 + op_result . extra_cycles)"));
+    assert!(output.contains("self . lda (AddressingMode :: AbsoluteX)"));
+}
+
+#[test]
+fn test_disassemble_op_keeps_length() {
+    let output = disassemble_op2(quote! {
+        address, operands {
+            jmp: 0x4C => Absolute (3) [3];
+        }
+    }).to_string();
+    assert!(output.contains("0x4C =>"));
+    assert!(output.contains("length : 3"));
+    assert!(output.contains("stringify ! (jmp)"));
+}
+
+#[test]
+fn test_call_op_guards_unofficial_opcodes() {
+    let output = call_op2(quote! {
+        opcode {
+            nop: 0xEA => Implied (1) [2];
+            *nop: 0x1A => Implied (1) [2];
+        }
+    }).to_string();
+    assert!(output.contains("0xEA =>"));
+    assert!(output.contains("0x1A if self . allow_unofficial_opcodes =>"));
+}
+
+#[test]
+fn test_disassemble_op_marks_unofficial_opcodes() {
+    let output = disassemble_op2(quote! {
+        address, operands {
+            *lax: 0xA7 => ZeroPage (2) [3];
+        }
+    }).to_string();
+    assert!(output.contains("unofficial : true"));
+}
+
+#[test]
+fn test_call_op_fallback() {
+    let output = call_op2(quote! {
+        opcode {
+            bra: 0x80 => Relative (2) [2];
+        } else self.handle_nmos_opcode(opcode)
+    }).to_string();
+    assert!(output.contains("_ => self . handle_nmos_opcode (opcode)"));
+    assert!(!output.contains("InvalidOpcode"));
+
+    let output = call_op2(quote! {
+        opcode {
+            nop: 0xEA => Implied (1) [2];
+        }
+    }).to_string();
+    assert!(output.contains("_ => Err (EmulationError :: InvalidOpcode (opcode))"));
+}
+
+#[test]
+fn test_opcode_table() {
+    let output = opcode_table2(quote! {
+        {
+            *lax: 0xA7 => ZeroPage (2) [3];
+        }
+    }).to_string();
+    assert!(output.contains("[None ; 256]"));
+    assert!(output.contains("table [0xA7 as usize] = Some (OpcodeInfo {"));
+    assert!(output.contains("cycles : 3"));
+    assert!(output.contains("unofficial : true"));
+
+    let output = opcode_table2(quote! {
+        {
+            bra: 0x80 => Relative (2) [2];
+        } else OPCODES
+    }).to_string();
+    assert!(output.contains("let mut table : [Option < OpcodeInfo > ; 256] = OPCODES ;"));
+}
+
+#[test]
+fn test_rejects_duplicate_opcodes() {
+    let output = call_op2(quote! {
+        opcode {
+            lda: 0xA9 => Immediate (2) [2];
+            ldx: 0xA9 => Immediate (2) [2];
+        }
+    }).to_string();
+    assert!(output.contains("compile_error ! { \"duplicate opcode 0xA9\" }"));
+    assert!(output.contains("compile_error ! { \"first defined here\" }"));
+}
+
+#[test]
+fn test_rejects_unknown_addressing_modes() {
+    let output = disassemble_op2(quote! {
+        address, operands {
+            lda: 0xA9 => Immediat (2) [2];
+        }
+    }).to_string();
+    assert!(output.contains("compile_error ! { \"unknown addressing mode `Immediat`\" }"));
+}
+
+#[test]
+fn test_rejects_wrong_lengths() {
+    let output = opcode_table2(quote! {
+        {
+            jmp: 0x4C => Absolute (2) [3];
+        }
+    }).to_string();
+    assert!(output.contains("compile_error ! { \"Absolute instructions are 3 bytes long, not 2\" }"));
+}
+
+#[test]
+fn test_reports_syntax_errors() {
+    let output = call_op2(quote! {
+        opcode {
+            lda 0xA9 => Immediate (2) [2];
+        }
+    }).to_string();
+    assert!(output.starts_with("compile_error !"));
+
+    let output = call_op2(quote! {
+        opcode {
+            lda: 0x1A9 => Immediate (2) [2];
+        }
+    }).to_string();
+    assert!(output.contains("compile_error ! { \"number too large to fit in target type\" }"));
+}
+
+#[test]
+fn test_call_op_reads_after_implied_opcodes() {
+    let output = call_op2(quote! {
+        opcode {
+            tax: 0xAA => Implied (1) [2];
+        }
+    }).to_string();
+    assert!(output.contains("self . dummy_read (self . register_pc) ?"));
+
+    // One cycle instructions only fetch their opcode
+    let output = call_op2(quote! {
+        opcode {
+            nop: 0x03 => Implied (1) [1];
+            lda: 0xA9 => Immediate (2) [2];
+        }
+    }).to_string();
+    assert!(!output.contains("dummy_read"));
+}